@@ -1,9 +1,16 @@
-use std::sync::{Arc, Mutex};
+use std::{
+    path::PathBuf,
+    sync::{Arc, Mutex},
+};
 
-use clap::Parser;
+use clap::{CommandFactory, Parser};
 use lazy_static::lazy_static;
+use mavlink::ardupilotmega::{MavAutopilot, MavType};
+use serde::de::{value::MapDeserializer, DeserializeOwned};
 use tracing::*;
 
+use crate::config::Config;
+
 #[derive(Parser, Debug)]
 #[command(
     version = env!("CARGO_PKG_VERSION"),
@@ -12,12 +19,12 @@ use tracing::*;
 )]
 struct Args {
     /// Space-separated list of endpoints.
-    /// At least one endpoint is required.
+    /// At least one endpoint is required, unless a configuration file is used.
     /// Possible endpoints types are:
     ///
     /// udpc:dest_ip:port (udp, client mode)
     ///
-    /// udpb:broadcast_ip:port (udp, broadcast mode)
+    /// udpb:broadcast_ip:port (udp, broadcast mode, switching to the first peer that responds)
     ///
     /// tcps:listen_ip:port (tcp, server mode)
     ///
@@ -25,15 +32,60 @@ struct Args {
     ///
     /// serial:port:baudrate (serial)
     ///
-    /// udps:listen_ip:port (udp, server mode)
+    /// udps:listen_ip:port (udp, server mode, sending to the clients heard from in the last 10
+    /// seconds, or in the seconds set with the "client_timeout" option, e.g.:
+    /// udps:0.0.0.0:14550?client_timeout=30)
+    ///
+    /// udpm:group_ip:port[:interface] (udp, multicast mode, with IPv6 groups in brackets and the
+    /// interface as an address for IPv4 groups or an index for IPv6 groups, sending with the time to
+    /// live set with the "ttl" option, 1 by default, and delivering to the members on this same host
+    /// with the "loop" option, e.g.: udpm:239.255.0.1:14550?ttl=4&loop=true)
+    ///
+    /// ws:listen_ip:port (websocket, server mode, one MAVLink frame per binary message)
+    ///
+    /// wsjson:listen_ip:port (websocket, server mode, one decoded message as JSON per text message)
+    ///
+    /// unixs:path (unix stream socket, server mode, with the socket file permissions set in octal
+    /// with the "mode" option, e.g.: unixs:/run/mavlink.sock?mode=660)
+    ///
+    /// unixc:path (unix stream socket, client mode)
+    ///
+    /// unixds:path (unix datagram socket, server mode, replying to the clients bound to a path,
+    /// with the same "mode" and "client_timeout" options as unixs and udps)
+    ///
+    /// unixdc:path (unix datagram socket, client mode)
+    ///
+    /// tlogw:directory (telemetry log recording, starting a new file after the megabytes set with
+    /// the "max_size" option or the minutes set with the "max_duration" option, e.g.:
+    /// tlogw:/logs?max_size=100&max_duration=60)
+    ///
+    /// tlogr:path (telemetry log replay, with the "speed" multiplier, "loop" to restart at the end,
+    /// and the "start" and "end" offsets in seconds as options, e.g.:
+    /// tlogr:/logs/flight.tlog?speed=2&loop=true&start=10)
+    ///
+    /// Addresses can be IPv4, IPv6 in brackets or hostnames, e.g.: udpc:[::1]:14550 or
+    /// tcpc:gcs.local:5760
+    ///
+    /// Any endpoint can have message filters appended as a query, with allow and block lists of
+    /// message ids (by number or name), source system ids and source component ids, optionally
+    /// restricted to one direction with the "in_" or "out_" prefix, e.g.:
+    ///
+    /// udpc:192.168.2.1:14550?out_block_msgid=ATTITUDE,VFR_HUD&in_allow_sysid=1
     #[arg(
-        required = true,
+        required_unless_present = "config",
         num_args = 1..,
         value_delimiter = ' ',
         value_parser = endpoints_parser,
     )]
     endpoints: Vec<String>,
 
+    /// Path of a TOML configuration file with endpoints, hub and log options.
+    /// Its endpoints are added to the ones from the command line, and the command line flags
+    /// take precedence over its options.
+    /// The endpoints are reloaded when the file changes or when SIGHUP is received.
+    #[arg(long)]
+    config: Option<PathBuf>,
+
     /// Turns all log categories up to Debug, for more information check RUST_LOG env variable.
     #[arg(short, long)]
     verbose: bool,
@@ -42,37 +94,181 @@ struct Args {
     #[arg(long)]
     enable_tracing_level_log_file: bool,
 
-    /// Specifies the path in which the logs will be stored.
-    #[arg(long, default_value = "./logs")]
+    /// Specifies the path in which the logs will be stored [default: ./logs]
+    #[arg(long)]
     log_path: Option<String>,
 
-    #[arg(long, default_value = "true")]
+    /// Drops REQUEST_DATA_STREAM messages and SET_MESSAGE_INTERVAL commands coming from ground
+    /// control stations, so multiple ground control stations can't fight over the stream rates
+    /// of the vehicle. Components that didn't send a HEARTBEAT yet are treated as ground control
+    /// stations.
+    #[arg(long)]
     streamreq_disable: bool,
+
+    /// System id of the hub, used in the messages it originates, like its HEARTBEAT [default: 1]
+    #[arg(long)]
+    system_id: Option<u8>,
+
+    /// Component id of the hub, used in the messages it originates, like its HEARTBEAT
+    /// [default: 191 (MAV_COMP_ID_ONBOARD_COMPUTER)]
+    #[arg(long)]
+    component_id: Option<u8>,
+
+    /// Frequency, in Hz, of the hub's HEARTBEAT, 0 disables it [default: 1]
+    #[arg(long, value_parser = heartbeat_frequency_parser)]
+    heartbeat_frequency: Option<f32>,
+
+    /// MAV_TYPE of the hub's HEARTBEAT [default: MAV_TYPE_ONBOARD_CONTROLLER]
+    #[arg(long, value_parser = mavlink_enum_parser::<MavType>)]
+    heartbeat_mavtype: Option<MavType>,
+
+    /// MAV_AUTOPILOT of the hub's HEARTBEAT [default: MAV_AUTOPILOT_INVALID]
+    #[arg(long, value_parser = mavlink_enum_parser::<MavAutopilot>)]
+    heartbeat_autopilot: Option<MavAutopilot>,
+
+    /// Address of the HTTP server for the management API and Prometheus metrics (/metrics), e.g.: 0.0.0.0:8080.
+    /// The API is disabled if not set.
+    #[arg(long)]
+    web_server: Option<std::net::SocketAddr>,
 }
 
+/// Validates the endpoint, normalizing its kind
 #[instrument(level = "debug")]
-fn endpoints_parser(endpoint: &str) -> Result<String, String> {
-    let endpoint = endpoint.to_lowercase();
+pub fn endpoints_parser(endpoint: &str) -> Result<String, String> {
+    let (endpoint, query) = endpoint.split_once('?').unwrap_or((endpoint, ""));
+    let (kind, rest) = endpoint.split_once(':').unwrap_or((endpoint, ""));
+
+    // Only the kind is case-insensitive, serial port names (e.g. /dev/ttyACM0) are not
+    let kind = kind.to_lowercase();
+
+    match kind.as_str() {
+        "udps" | "udpc" | "tcps" | "tcpc" | "ws" | "wsjson" => address_parser(rest)?,
+        "udpb" => {
+            address_parser(rest)?;
+            if rest.starts_with('[') {
+                return Err(
+                    "IPv6 has no broadcast, use a multicast (udpm) endpoint instead".to_string(),
+                );
+            }
+        }
+        "udpm" => {
+            if let Err(error) = crate::drivers::udp::multicast::parse_address(rest) {
+                return Err(format!("Invalid multicast endpoint: {error:#}"));
+            }
+        }
+        "serial" => {
+            let Some((port_name, baud_rate)) = rest.rsplit_once(':') else {
+                return Err("Wrong endpoint format, expected serial:port:baudrate".to_string());
+            };
+            if port_name.is_empty() {
+                return Err("Missing port for serial endpoint".to_string());
+            }
+            if baud_rate.parse::<u32>().is_err() {
+                return Err(format!(
+                    "Invalid baud rate: {baud_rate:?} for serial endpoint"
+                ));
+            }
+        }
+        "tlogw" | "tlogr" | "unixs" | "unixc" | "unixds" | "unixdc" => {
+            if rest.is_empty() {
+                return Err(format!("Missing path for {kind} endpoint"));
+            }
+        }
+        _ => {
+            return Err(format!(
+                "Unknown kind: {kind:?} for endpoint, it should be one of: udps, udpc, udpb, udpm, tcps, tcpc, ws, wsjson, unixs, unixc, unixds, unixdc, serial, tlogw, or tlogr"
+            ))
+        }
+    }
 
-    let mut split = endpoint.split(':');
-    if split.clone().count() != 3 {
-        return Err("Wrong endpoint format".to_string());
+    if let Err(error) = crate::drivers::parse_query(&kind, query) {
+        return Err(format!("Invalid endpoint query: {error}"));
     }
 
-    let kind = split.next().expect(
-        "Endpoint should start with one of the kinds: udps, udpc, udpb, tcps, tcpc, or serial",
-    );
-    if !matches!(kind, "udps" | "udpc" | "udpb" | "tcps" | "tcpc" | "serial") {
-        return Err(format!("Unknown kind: {kind:?} for endpoint"));
+    if query.is_empty() {
+        return Ok(format!("{kind}:{rest}"));
+    }
+
+    Ok(format!("{kind}:{rest}?{query}"))
+}
+
+/// Validates a "host:port" address, where the host is an IPv4 address, an IPv6 address in
+/// brackets or a hostname
+#[instrument(level = "debug")]
+fn address_parser(address: &str) -> Result<(), String> {
+    let Some((host, port)) = address.rsplit_once(':') else {
+        return Err(format!("Missing port in address: {address:?}"));
+    };
+
+    if port.parse::<u16>().is_err() {
+        return Err(format!("Invalid port: {port:?}"));
+    }
+
+    if let Some(ip) = host
+        .strip_prefix('[')
+        .and_then(|host| host.strip_suffix(']'))
+    {
+        return match ip.parse::<std::net::Ipv6Addr>() {
+            Ok(_) => Ok(()),
+            Err(_) => Err(format!("Invalid IPv6 address: {ip:?}")),
+        };
+    }
+
+    if host.contains(':') {
+        return Err(format!(
+            "IPv6 addresses should be in brackets, e.g.: [::1]:14550, got: {host:?}"
+        ));
+    }
+
+    if host.parse::<std::net::Ipv4Addr>().is_ok() {
+        return Ok(());
+    }
+
+    let is_hostname = !host
+        .chars()
+        .all(|char| char.is_ascii_digit() || char == '.')
+        && host.split('.').all(|label| {
+            !label.is_empty()
+                && label.len() <= 63
+                && !label.starts_with('-')
+                && !label.ends_with('-')
+                && label
+                    .chars()
+                    .all(|char| char.is_ascii_alphanumeric() || char == '-')
+        });
+    if !is_hostname {
+        return Err(format!("Invalid host: {host:?}"));
+    }
+
+    Ok(())
+}
+
+#[instrument(level = "debug")]
+fn heartbeat_frequency_parser(frequency: &str) -> Result<f32, String> {
+    match frequency.parse::<f32>() {
+        Ok(frequency) if frequency >= 0. && frequency.is_finite() => Ok(frequency),
+        _ => Err(format!(
+            "Invalid heartbeat frequency: {frequency:?}, it should be a positive number, or 0 to disable it"
+        )),
     }
+}
 
-    // Add your custom validation logic here if needed
-    Ok(endpoint)
+/// Parses a MAVLink enum from its name, e.g.: "MAV_TYPE_ONBOARD_CONTROLLER"
+pub fn mavlink_enum_parser<T: DeserializeOwned>(name: &str) -> Result<T, String> {
+    // MAVLink enums are tagged by their "type" field, like the messages
+    let name = name.to_uppercase();
+    let fields = MapDeserializer::<_, serde::de::value::Error>::new(std::iter::once((
+        "type",
+        name.as_str(),
+    )));
+
+    T::deserialize(fields).map_err(|error| format!("Invalid MAVLink enum value {name:?}: {error}"))
 }
 
 #[derive(Debug)]
 struct Manager {
     clap_matches: Args,
+    config: Config,
 }
 
 lazy_static! {
@@ -82,8 +278,23 @@ lazy_static! {
 
 impl Manager {
     fn new() -> Self {
+        let clap_matches = Args::parse();
+
+        let config = match &clap_matches.config {
+            Some(path) => Config::load(path).unwrap_or_else(|error| {
+                Args::command()
+                    .error(
+                        clap::error::ErrorKind::InvalidValue,
+                        format!("Failed to load config file {path:?}: {error:#}"),
+                    )
+                    .exit()
+            }),
+            None => Config::default(),
+        };
+
         Self {
-            clap_matches: Args::parse(),
+            clap_matches,
+            config,
         }
     }
 }
@@ -99,79 +310,107 @@ pub fn init() {
 
 #[instrument(level = "debug")]
 pub fn is_verbose() -> bool {
-    MANAGER.clap_matches.verbose
+    MANAGER.clap_matches.verbose || MANAGER.config.log.verbose
 }
 
 #[instrument(level = "debug")]
 pub fn is_tracing() -> bool {
-    MANAGER.clap_matches.enable_tracing_level_log_file
+    MANAGER.clap_matches.enable_tracing_level_log_file || MANAGER.config.log.tracing_level_log_file
 }
 
 /// Our log path
 
 #[instrument(level = "debug")]
 pub fn log_path() -> String {
-    let log_path =
-        MANAGER.clap_matches.log_path.clone().expect(
-            "Clap arg \"log-path\" should always be \"Some(_)\" because of the default value.",
-        );
+    let log_path = MANAGER
+        .clap_matches
+        .log_path
+        .clone()
+        .or_else(|| MANAGER.config.log.path.clone())
+        .unwrap_or_else(|| "./logs".to_string());
 
     shellexpand::full(&log_path)
         .expect("Failed to expand path")
         .to_string()
 }
 
+/// Endpoints from the configuration file followed by the ones from the command line, in the
+/// normalized format accepted by [`crate::drivers::create_driver`]
 #[instrument(level = "debug")]
-pub fn tcp_client_endpoints() -> Vec<String> {
-    get_endpoint_with_kind("tcpc")
+pub fn endpoints() -> Vec<String> {
+    MANAGER
+        .config
+        .endpoints
+        .iter()
+        .chain(MANAGER.clap_matches.endpoints.iter())
+        .cloned()
+        .collect()
 }
 
 #[instrument(level = "debug")]
-pub fn tcp_server_endpoints() -> Vec<String> {
-    get_endpoint_with_kind("tcps")
+pub fn command_line_endpoints() -> Vec<String> {
+    MANAGER.clap_matches.endpoints.clone()
 }
 
 #[instrument(level = "debug")]
-pub fn udp_client_endpoints() -> Vec<String> {
-    get_endpoint_with_kind("udpc")
+pub fn config_path() -> Option<std::path::PathBuf> {
+    MANAGER.clap_matches.config.clone()
 }
 
 #[instrument(level = "debug")]
-pub fn udp_server_endpoints() -> Vec<String> {
-    get_endpoint_with_kind("udps")
+pub fn hub_system_id() -> u8 {
+    MANAGER
+        .clap_matches
+        .system_id
+        .unwrap_or(MANAGER.config.hub.system_id)
 }
 
 #[instrument(level = "debug")]
-pub fn udp_broadcast_endpoints() -> Vec<String> {
-    get_endpoint_with_kind("udpb")
+pub fn hub_component_id() -> u8 {
+    MANAGER
+        .clap_matches
+        .component_id
+        .unwrap_or(MANAGER.config.hub.component_id)
 }
 
+/// Frequency of the hub's HEARTBEAT, where 0 means it is disabled
 #[instrument(level = "debug")]
-pub fn serial_endpoints() -> Vec<String> {
-    get_endpoint_with_kind("serial")
+pub fn hub_heartbeat_frequency() -> f32 {
+    MANAGER
+        .clap_matches
+        .heartbeat_frequency
+        .unwrap_or(MANAGER.config.hub.heartbeat_frequency)
 }
 
 #[instrument(level = "debug")]
-fn get_endpoint_with_kind(kind: &str) -> Vec<String> {
-    let mut endpoints = vec![];
-
-    for endpoint in MANAGER.clap_matches.endpoints.clone() {
-        let mut s = endpoint.split(':');
+pub fn hub_heartbeat_mavtype() -> MavType {
+    MANAGER
+        .clap_matches
+        .heartbeat_mavtype
+        .unwrap_or(MANAGER.config.hub.mavtype)
+}
 
-        let Some(this_kind) = s.next() else {
-            warn!("No kind for endpoint: {endpoint:?}");
-            continue;
-        };
+#[instrument(level = "debug")]
+pub fn hub_heartbeat_autopilot() -> MavAutopilot {
+    MANAGER
+        .clap_matches
+        .heartbeat_autopilot
+        .unwrap_or(MANAGER.config.hub.autopilot)
+}
 
-        if !this_kind.eq(kind) {
-            trace!("ignoring: {this_kind:?}");
-            continue;
-        }
+#[instrument(level = "debug")]
+pub fn hub_buffer_size() -> usize {
+    MANAGER.config.hub.buffer_size
+}
 
-        endpoints.push(s.clone().collect::<Vec<&str>>().join(":"));
-    }
+#[instrument(level = "debug")]
+pub fn streamreq_disable() -> bool {
+    MANAGER.clap_matches.streamreq_disable
+}
 
-    endpoints
+#[instrument(level = "debug")]
+pub fn web_server() -> Option<std::net::SocketAddr> {
+    MANAGER.clap_matches.web_server
 }
 
 // Return the command line used to start this application
@@ -187,3 +426,141 @@ pub fn command_line_string() -> String {
 pub fn command_line() -> String {
     format!("{:#?}", MANAGER.clap_matches)
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn mavlink_enum_is_parsed_by_name() {
+        assert_eq!(
+            mavlink_enum_parser::<MavType>("mav_type_gcs"),
+            Ok(MavType::MAV_TYPE_GCS)
+        );
+        assert_eq!(
+            mavlink_enum_parser::<MavAutopilot>("MAV_AUTOPILOT_PX4"),
+            Ok(MavAutopilot::MAV_AUTOPILOT_PX4)
+        );
+    }
+
+    #[test]
+    fn invalid_mavlink_enum_is_reported() {
+        let error = mavlink_enum_parser::<MavType>("MAV_TYPE_SPACESHIP").unwrap_err();
+
+        assert!(error.contains("\"MAV_TYPE_SPACESHIP\""), "{error}");
+        assert!(error.contains("MAV_TYPE_GCS"), "{error}");
+    }
+
+    #[test]
+    fn endpoint_kind_is_normalized() {
+        assert_eq!(
+            endpoints_parser("TCPC:127.0.0.1:5760"),
+            Ok("tcpc:127.0.0.1:5760".to_string())
+        );
+        assert_eq!(
+            endpoints_parser("Serial:/dev/ttyACM0:115200"),
+            Ok("serial:/dev/ttyACM0:115200".to_string())
+        );
+    }
+
+    #[test]
+    fn endpoint_accepts_ipv6_in_brackets() {
+        for endpoint in [
+            "udpc:[::1]:14550",
+            "udps:[::]:14550",
+            "tcpc:[fe80::1]:5760",
+            "tcps:[2001:db8::42]:5760",
+            "ws:[::]:8088",
+            "wsjson:[::1]:8089",
+        ] {
+            assert_eq!(endpoints_parser(endpoint), Ok(endpoint.to_string()));
+        }
+    }
+
+    #[test]
+    fn endpoint_options_depend_on_the_kind() {
+        assert!(endpoints_parser("udps:0.0.0.0:14550?client_timeout=30").is_ok());
+        assert!(endpoints_parser("udps:0.0.0.0:14550?client_timeout=0.5&in_allow_sysid=1").is_ok());
+        assert!(endpoints_parser("udps:0.0.0.0:14550?client_timeout=-1").is_err());
+        assert!(endpoints_parser("udpc:127.0.0.1:14550?client_timeout=30").is_err());
+        assert!(endpoints_parser("unixs:/run/mavlink.sock?mode=660").is_ok());
+        assert!(endpoints_parser("unixds:/run/mavlink.sock?mode=0o600&client_timeout=5").is_ok());
+        assert!(endpoints_parser("unixs:/run/mavlink.sock?mode=999").is_err());
+        assert!(endpoints_parser("unixc:/run/mavlink.sock?mode=660").is_err());
+        assert!(endpoints_parser("tlogr:/logs/a.tlog?speed=2&loop=true&start=10&end=60").is_ok());
+        assert!(endpoints_parser("tlogr:/logs/a.tlog?speed=0").is_err());
+        assert!(endpoints_parser("tlogr:/logs/a.tlog?loop=yes").is_err());
+        assert!(endpoints_parser("tlogr:/logs/a.tlog?start=-1").is_err());
+        assert!(endpoints_parser("tlogw:/logs?max_size=100&max_duration=60").is_ok());
+        assert!(endpoints_parser("tlogw:/logs?max_size=0").is_err());
+        assert!(endpoints_parser("tlogr:/logs/a.tlog?max_size=100").is_err());
+    }
+
+    #[test]
+    fn endpoint_accepts_hostnames() {
+        for endpoint in [
+            "tcpc:localhost:5760",
+            "udpc:gcs.local:14550",
+            "udpb:my-router:14550",
+        ] {
+            assert_eq!(endpoints_parser(endpoint), Ok(endpoint.to_string()));
+        }
+    }
+
+    #[test]
+    fn endpoint_rejects_invalid_addresses() {
+        for endpoint in [
+            "udpc:::1:14550",
+            "udpc:[::1:14550",
+            "udpc:[not-an-ip]:14550",
+            "udpc:127.0.0.1",
+            "udpc:127.0.0.1:99999",
+            "udpc:127.0.0.1:",
+            "tcpc::5760",
+            "tcpc:300.1.1.1:5760",
+            "tcpc:bad_host:5760",
+            "udpb:[ff02::1]:14550",
+        ] {
+            assert!(endpoints_parser(endpoint).is_err(), "{endpoint:?}");
+        }
+    }
+
+    #[test]
+    fn endpoint_keeps_filter_query() {
+        assert_eq!(
+            endpoints_parser("UDPC:[::1]:14550?out_block_msgid=ATTITUDE"),
+            Ok("udpc:[::1]:14550?out_block_msgid=ATTITUDE".to_string())
+        );
+        assert!(endpoints_parser("udpc:[::1]:14550?out_block_msgid=NOT_A_MESSAGE").is_err());
+    }
+
+    #[test]
+    fn endpoint_multicast() {
+        assert!(endpoints_parser("udpm:239.255.0.1:14550").is_ok());
+        assert!(endpoints_parser("udpm:239.255.0.1:14550:192.168.2.2").is_ok());
+        assert!(endpoints_parser("udpm:[ff02::1]:14550:2").is_ok());
+        assert!(endpoints_parser("udpm:192.168.2.1:14550").is_err());
+        assert!(endpoints_parser("udpm:[ff02::1]:14550:eth0").is_err());
+        assert!(endpoints_parser("udpm:239.255.0.1:14550?ttl=4&loop=true").is_ok());
+        assert!(endpoints_parser("udpm:239.255.0.1:14550?ttl=256").is_err());
+        assert!(endpoints_parser("udpc:127.0.0.1:14550?ttl=4").is_err());
+    }
+
+    #[test]
+    fn endpoint_serial_tlog_and_unix() {
+        assert!(endpoints_parser("serial:COM3:57600").is_ok());
+        assert!(endpoints_parser("serial:/dev/ttyACM0:fast").is_err());
+        assert!(endpoints_parser("serial::57600").is_err());
+        assert!(endpoints_parser("tlogw:/tmp/logs").is_ok());
+        assert!(endpoints_parser("tlogr:").is_err());
+        assert!(endpoints_parser("unixs:/run/mavlink.sock").is_ok());
+        assert!(endpoints_parser("unixdc:/run/mavlink.sock").is_ok());
+        assert!(endpoints_parser("unixc:").is_err());
+    }
+
+    #[test]
+    fn endpoint_rejects_unknown_kind() {
+        assert!(endpoints_parser("http:127.0.0.1:8080").is_err());
+        assert!(endpoints_parser("127.0.0.1:8080").is_err());
+    }
+}
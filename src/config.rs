@@ -0,0 +1,176 @@
+use std::{
+    path::{Path, PathBuf},
+    sync::Arc,
+    time::SystemTime,
+};
+
+use anyhow::{anyhow, Context, Result};
+use mavlink::ardupilotmega::{MavAutopilot, MavType};
+use serde::{de::DeserializeOwned, Deserialize, Deserializer};
+use tokio::signal::unix::{signal, SignalKind};
+use tracing::*;
+
+use crate::{cli, hub::Hub};
+
+/// How often the configuration file is checked for changes
+const WATCH_PERIOD: std::time::Duration = std::time::Duration::from_secs(1);
+
+/// Configuration file, complementing the command line arguments, e.g.:
+///
+/// ```toml
+/// endpoints = [
+///     "tcps:0.0.0.0:5760",
+///     "udpc:192.168.2.1:14550?out_block_msgid=ATTITUDE",
+///     "serial:/dev/ttyACM0:115200",
+/// ]
+///
+/// [hub]
+/// system_id = 1
+/// component_id = 191
+/// heartbeat_frequency = 1.0 # 0 disables it
+/// mavtype = "MAV_TYPE_ONBOARD_CONTROLLER"
+/// autopilot = "MAV_AUTOPILOT_INVALID"
+/// buffer_size = 100
+///
+/// [log]
+/// verbose = false
+/// path = "./logs"
+/// tracing_level_log_file = false
+/// ```
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    /// Endpoints in the same format accepted by the CLI
+    pub endpoints: Vec<String>,
+    pub hub: HubConfig,
+    pub log: LogConfig,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct HubConfig {
+    pub system_id: u8,
+    pub component_id: u8,
+    /// Frequency, in Hz, of the hub's own HEARTBEAT, 0 disables it
+    pub heartbeat_frequency: f32,
+    #[serde(deserialize_with = "deserialize_mavlink_enum")]
+    pub mavtype: MavType,
+    #[serde(deserialize_with = "deserialize_mavlink_enum")]
+    pub autopilot: MavAutopilot,
+    /// Amount of messages the hub holds for slow drivers before they start to lag
+    pub buffer_size: usize,
+}
+
+impl Default for HubConfig {
+    fn default() -> Self {
+        Self {
+            system_id: 1,
+            component_id: mavlink::ardupilotmega::MavComponent::MAV_COMP_ID_ONBOARD_COMPUTER as u8,
+            heartbeat_frequency: 1.,
+            mavtype: MavType::MAV_TYPE_ONBOARD_CONTROLLER,
+            autopilot: MavAutopilot::MAV_AUTOPILOT_INVALID,
+            buffer_size: 100,
+        }
+    }
+}
+
+/// Logging options, overridden by their command line counterparts when those are used
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct LogConfig {
+    pub verbose: bool,
+    pub path: Option<String>,
+    pub tracing_level_log_file: bool,
+}
+
+impl Config {
+    /// Loads and validates the configuration file, normalizing its endpoints
+    #[instrument(level = "debug")]
+    pub fn load(path: &Path) -> Result<Self> {
+        let content = std::fs::read_to_string(path).context("Failed to read config file")?;
+        let mut config: Config = toml::from_str(&content)?;
+
+        config.endpoints = config
+            .endpoints
+            .iter()
+            .map(|endpoint| {
+                cli::endpoints_parser(endpoint)
+                    .map_err(|error| anyhow!("Invalid endpoint {endpoint:?}: {error}"))
+            })
+            .collect::<Result<_>>()?;
+
+        let heartbeat_frequency = config.hub.heartbeat_frequency;
+        if heartbeat_frequency < 0. || !heartbeat_frequency.is_finite() {
+            return Err(anyhow!(
+                "Invalid heartbeat frequency: {heartbeat_frequency}, it should be a positive number, or 0 to disable it"
+            ));
+        }
+
+        if config.hub.buffer_size == 0 {
+            return Err(anyhow!("Invalid buffer size: it should be at least 1"));
+        }
+
+        Ok(config)
+    }
+}
+
+/// Deserializes a MAVLink enum from its name, e.g.: "MAV_TYPE_ONBOARD_CONTROLLER"
+fn deserialize_mavlink_enum<'de, D, T>(deserializer: D) -> Result<T, D::Error>
+where
+    D: Deserializer<'de>,
+    T: DeserializeOwned,
+{
+    let name = String::deserialize(deserializer)?;
+    cli::mavlink_enum_parser(&name).map_err(serde::de::Error::custom)
+}
+
+/// Reloads the configuration file when it changes or when SIGHUP is received, updating the hub
+/// endpoints without touching the drivers of unchanged endpoints.
+/// Only the endpoints are reloaded, the hub and log options require a restart.
+#[instrument(level = "debug", skip(hub))]
+pub async fn watch(path: PathBuf, hub: Arc<Hub>) -> Result<()> {
+    let mut sighup = signal(SignalKind::hangup())?;
+    let mut interval = tokio::time::interval(WATCH_PERIOD);
+    let mut last_modified = modified(&path);
+
+    loop {
+        tokio::select! {
+            _ = sighup.recv() => {
+                info!("Received SIGHUP, reloading config file {path:?}");
+            }
+            _ = interval.tick() => {
+                let modified = modified(&path);
+                if modified == last_modified {
+                    continue;
+                }
+                last_modified = modified;
+
+                info!("Config file {path:?} changed, reloading it");
+            }
+        }
+
+        let config = match Config::load(&path) {
+            Ok(config) => config,
+            Err(error) => {
+                error!("Failed to reload config file {path:?}, keeping the current endpoints: {error:#}");
+                continue;
+            }
+        };
+
+        let endpoints: Vec<String> = config
+            .endpoints
+            .into_iter()
+            .chain(cli::command_line_endpoints())
+            .collect();
+
+        if let Err(error) = hub.update_endpoints(&endpoints).await {
+            error!("Failed to update endpoints: {error:?}");
+        }
+    }
+}
+
+fn modified(path: &Path) -> Option<SystemTime> {
+    std::fs::metadata(path)
+        .and_then(|metadata| metadata.modified())
+        .ok()
+}
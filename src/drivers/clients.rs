@@ -0,0 +1,158 @@
+use std::{
+    collections::HashMap,
+    fmt::Debug,
+    hash::Hash,
+    sync::atomic::{AtomicU64, Ordering},
+};
+
+use tokio::{
+    sync::{RwLock, RwLockReadGuard},
+    time::{Duration, Instant},
+};
+use tracing::*;
+
+use crate::drivers::stats::DriverStatsCounters;
+
+/// Clients not heard from for longer than this stop receiving messages, unless the endpoint sets
+/// its own `client_timeout`
+pub const DEFAULT_CLIENT_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// How often the silent clients are looked for
+const EXPIRY_PERIOD: Duration = Duration::from_secs(1);
+
+/// A client of a datagram server
+#[derive(Debug)]
+pub struct Client {
+    /// Name of the client, which is also the origin of its messages
+    pub name: String,
+    /// When the client was last heard from, in milliseconds since the table was created
+    last_seen: AtomicU64,
+}
+
+/// Clients of a datagram server (e.g. UDP or Unix datagram), which are only known from what they
+/// send, so they are forgotten after some time of silence.
+///
+/// Known clients are refreshed and iterated under the read lock, the write lock is only taken to
+/// add new clients and to remove the silent ones.
+#[derive(Debug)]
+pub struct ClientTable<A> {
+    clients: RwLock<HashMap<A, Client>>,
+    timeout: Duration,
+    created: Instant,
+}
+
+impl<A: Eq + Hash + Debug> ClientTable<A> {
+    pub fn new(timeout: Duration) -> Self {
+        Self {
+            clients: RwLock::new(HashMap::new()),
+            timeout,
+            created: Instant::now(),
+        }
+    }
+
+    fn now(&self) -> u64 {
+        self.created.elapsed().as_millis() as u64
+    }
+
+    /// Refreshes the client that was just heard from, adding it if it is new
+    pub async fn touch(&self, addr: A, name: &str, stats: &DriverStatsCounters) {
+        let now = self.now();
+
+        if let Some(client) = self.clients.read().await.get(&addr) {
+            client.last_seen.store(now, Ordering::Relaxed);
+            return;
+        }
+
+        self.clients
+            .write()
+            .await
+            .entry(addr)
+            .or_insert_with_key(|addr| {
+                debug!("Client added: {addr:?}");
+                stats.add_peer(name);
+
+                Client {
+                    name: name.to_string(),
+                    last_seen: AtomicU64::new(now),
+                }
+            });
+    }
+
+    /// Known clients, which are neither added nor removed while they are borrowed
+    pub async fn read(&self) -> RwLockReadGuard<'_, HashMap<A, Client>> {
+        self.clients.read().await
+    }
+
+    /// Removes the clients that went silent for longer than the timeout
+    pub async fn expire(&self, stats: &DriverStatsCounters) {
+        let now = self.now();
+        let timeout = self.timeout.as_millis() as u64;
+        let is_silent = |client: &Client| {
+            now.saturating_sub(client.last_seen.load(Ordering::Relaxed)) >= timeout
+        };
+
+        if !self.clients.read().await.values().any(is_silent) {
+            return;
+        }
+
+        self.clients.write().await.retain(|addr, client| {
+            if !is_silent(client) {
+                return true;
+            }
+
+            debug!(
+                "Client removed after {:?} of silence: {addr:?}",
+                self.timeout
+            );
+            stats.remove_peer(&client.name);
+            false
+        });
+    }
+
+    /// Removes the silent clients periodically, never returning
+    pub async fn expiry_task(&self, stats: &DriverStatsCounters) {
+        let mut interval = tokio::time::interval(EXPIRY_PERIOD.min(self.timeout));
+
+        loop {
+            interval.tick().await;
+            self.expire(stats).await;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn silent_clients_expire() {
+        let clients = ClientTable::new(Duration::from_millis(200));
+        let stats = DriverStatsCounters::default();
+
+        clients.touch(1, "silent", &stats).await;
+        clients.touch(2, "talkative", &stats).await;
+        assert_eq!(stats.snapshot().peers.len(), 2);
+
+        tokio::time::sleep(Duration::from_millis(120)).await;
+        clients.touch(2, "talkative", &stats).await;
+        tokio::time::sleep(Duration::from_millis(120)).await;
+        clients.expire(&stats).await;
+
+        let remaining = clients.read().await;
+        assert_eq!(remaining.len(), 1);
+        assert_eq!(remaining[&2].name, "talkative");
+        assert_eq!(stats.snapshot().peers, vec!["talkative".to_string()]);
+    }
+
+    #[tokio::test]
+    async fn touching_a_known_client_keeps_its_name() {
+        let clients = ClientTable::new(DEFAULT_CLIENT_TIMEOUT);
+        let stats = DriverStatsCounters::default();
+
+        clients.touch("addr", "first", &stats).await;
+        clients.touch("addr", "second", &stats).await;
+
+        assert_eq!(clients.read().await["addr"].name, "first");
+        assert_eq!(stats.snapshot().peers.len(), 1);
+    }
+}
@@ -0,0 +1,295 @@
+use mavlink::ardupilotmega::MavMessage;
+use tracing::*;
+
+use crate::protocol::MAVLinkMessageRaw;
+
+const MAV_STX_V1: u8 = 0xFE;
+const MAV_STX_V2: u8 = 0xFD;
+const MAVLINK_IFLAG_SIGNED: u8 = 0x01;
+const V1_HEADER_SIZE: usize = 6; // Including the STX byte
+const V2_HEADER_SIZE: usize = 10; // Including the STX byte
+const CHECKSUM_SIZE: usize = 2;
+const V2_SIGNATURE_SIZE: usize = 13;
+
+/// Size of the header of a frame starting with the given STX, including the STX itself
+pub fn header_size(stx: u8) -> Option<usize> {
+    match stx {
+        MAV_STX_V1 => Some(V1_HEADER_SIZE),
+        MAV_STX_V2 => Some(V2_HEADER_SIZE),
+        _ => None,
+    }
+}
+
+/// Size of the whole frame starting at the given bytes, which should contain at least its header
+pub fn frame_size(bytes: &[u8]) -> Option<usize> {
+    let header_size = header_size(*bytes.first()?)?;
+    if bytes.len() < header_size {
+        return None;
+    }
+
+    let payload_length = bytes[1] as usize;
+    let signature_size = match bytes[0] {
+        MAV_STX_V2 if bytes[2] & MAVLINK_IFLAG_SIGNED != 0 => V2_SIGNATURE_SIZE,
+        _ => 0,
+    };
+
+    Some(header_size + payload_length + CHECKSUM_SIZE + signature_size)
+}
+
+/// Splits a byte stream (e.g. TCP or Serial) into MAVLink frames, keeping leftover bytes between
+/// reads and resynchronizing on the STX magic after garbage.
+/// Both MAVLink v1 and v2 frames are detected, per frame.
+#[derive(Debug, Default)]
+pub struct StreamDecoder {
+    buffer: Vec<u8>,
+    bytes_discarded: u64,
+    /// Bytes discarded since the last [`StreamDecoder::take_bytes_discarded`]
+    new_bytes_discarded: u64,
+    parse_errors: u64,
+}
+
+impl StreamDecoder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Appends bytes read from the stream to the internal buffer
+    pub fn push(&mut self, bytes: &[u8]) {
+        self.buffer.extend_from_slice(bytes);
+    }
+
+    /// Total amount of bytes that were discarded because they were not part of a valid frame
+    pub fn bytes_discarded(&self) -> u64 {
+        self.bytes_discarded
+    }
+
+    /// Amount of bytes discarded since the last call
+    pub fn take_bytes_discarded(&mut self) -> u64 {
+        std::mem::take(&mut self.new_bytes_discarded)
+    }
+
+    /// Amount of invalid frames found since the last call
+    pub fn take_parse_errors(&mut self) -> u64 {
+        std::mem::take(&mut self.parse_errors)
+    }
+
+    /// Returns the next complete frame from the buffer, or `None` if more bytes are needed
+    pub async fn next_frame(&mut self) -> Option<MAVLinkMessageRaw> {
+        loop {
+            let Some(start) = self
+                .buffer
+                .iter()
+                .position(|&byte| matches!(byte, MAV_STX_V1 | MAV_STX_V2))
+            else {
+                self.discard(self.buffer.len());
+                return None;
+            };
+            self.discard(start);
+
+            // Wait for the whole header
+            let frame_size = frame_size(&self.buffer)?;
+
+            if self.buffer.len() < frame_size {
+                return None;
+            }
+
+            let mut frame = &self.buffer[..frame_size];
+            let result = match self.buffer[0] {
+                MAV_STX_V1 => mavlink::read_v1_raw_message_async::<MavMessage, _>(&mut frame)
+                    .await
+                    .map(MAVLinkMessageRaw::from),
+                _ => mavlink::read_v2_raw_message_async::<MavMessage, _>(&mut frame)
+                    .await
+                    .map(MAVLinkMessageRaw::from),
+            };
+
+            match result {
+                // The reader skips bytes until it finds a valid frame, so make sure the frame it
+                // found is the one starting at our STX
+                Ok(message) if self.buffer.starts_with(message.raw_bytes()) => {
+                    let message = message.with_frame(&self.buffer[..frame_size]);
+                    self.buffer.drain(..frame_size);
+                    return Some(message);
+                }
+                Ok(_) | Err(_) => {
+                    trace!("Invalid MAVLink frame, resynchronizing...");
+                    self.parse_errors += 1;
+                    self.discard(1);
+                }
+            }
+        }
+    }
+
+    fn discard(&mut self, amount: usize) {
+        if amount == 0 {
+            return;
+        }
+
+        self.buffer.drain(..amount);
+        self.bytes_discarded += amount as u64;
+        self.new_bytes_discarded += amount as u64;
+        trace!(
+            "Discarded {amount} bytes, {} in total",
+            self.bytes_discarded
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use mavlink::{
+        ardupilotmega::{MavType, HEARTBEAT_DATA},
+        MavlinkVersion, MessageData,
+    };
+
+    use super::*;
+    use crate::protocol::fixtures::{frame, heartbeat, heartbeat_frame};
+
+    fn v1_frame(sequence: u8) -> Vec<u8> {
+        heartbeat_frame(MavlinkVersion::V1, sequence)
+    }
+
+    fn v2_frame(sequence: u8) -> Vec<u8> {
+        heartbeat_frame(MavlinkVersion::V2, sequence)
+    }
+
+    /// CRC-16/MCRF4XX, as used by MAVLink
+    fn crc(bytes: &[u8]) -> u16 {
+        bytes.iter().fold(0xFFFF, |crc: u16, &byte| {
+            let mut tmp = byte ^ (crc & 0xFF) as u8;
+            tmp ^= tmp << 4;
+            let tmp = tmp as u16;
+            (crc >> 8) ^ (tmp << 8) ^ (tmp << 3) ^ (tmp >> 4)
+        })
+    }
+
+    /// A v2 frame with the signed flag and a (dummy) signature appended
+    fn signed_v2_frame(sequence: u8) -> Vec<u8> {
+        let mut frame = v2_frame(sequence);
+        frame.truncate(frame.len() - CHECKSUM_SIZE);
+        frame[2] |= MAVLINK_IFLAG_SIGNED;
+
+        let mut checksummed = frame[1..].to_vec();
+        checksummed.push(HEARTBEAT_DATA::EXTRA_CRC);
+        frame.extend_from_slice(&crc(&checksummed).to_le_bytes());
+        frame.extend_from_slice(&[0xAA; V2_SIGNATURE_SIZE]);
+        frame
+    }
+
+    async fn decode_all(decoder: &mut StreamDecoder) -> Vec<MAVLinkMessageRaw> {
+        let mut frames = vec![];
+        while let Some(frame) = decoder.next_frame().await {
+            frames.push(frame);
+        }
+        frames
+    }
+
+    #[tokio::test]
+    async fn frame_split_across_reads() {
+        let frame = v2_frame(7);
+        let (first, second) = frame.split_at(5);
+        let mut decoder = StreamDecoder::new();
+
+        decoder.push(first);
+        assert!(decoder.next_frame().await.is_none());
+
+        decoder.push(second);
+        let frames = decode_all(&mut decoder).await;
+        assert_eq!(frames.len(), 1);
+        assert_eq!(frames[0].raw_bytes(), frame.as_slice());
+        assert_eq!(decoder.bytes_discarded(), 0);
+    }
+
+    #[tokio::test]
+    async fn several_frames_in_one_read() {
+        let mut decoder = StreamDecoder::new();
+        for sequence in 0..3 {
+            decoder.push(&v2_frame(sequence));
+        }
+
+        let sequences: Vec<u8> = decode_all(&mut decoder)
+            .await
+            .iter()
+            .map(|frame| frame.sequence())
+            .collect();
+        assert_eq!(sequences, vec![0, 1, 2]);
+    }
+
+    #[tokio::test]
+    async fn garbage_before_stx_is_discarded() {
+        let mut decoder = StreamDecoder::new();
+        decoder.push(&[0x00, 0x11, 0x22]);
+        decoder.push(&v2_frame(1));
+
+        let frames = decode_all(&mut decoder).await;
+        assert_eq!(frames.len(), 1);
+        assert_eq!(decoder.bytes_discarded(), 3);
+        assert_eq!(decoder.take_bytes_discarded(), 3);
+        assert_eq!(decoder.take_bytes_discarded(), 0);
+        assert_eq!(decoder.take_parse_errors(), 0);
+    }
+
+    #[tokio::test]
+    async fn false_stx_resynchronizes() {
+        let mut decoder = StreamDecoder::new();
+        // A v2 STX with a short payload length, followed by bytes that aren't a valid frame
+        decoder.push(&[
+            MAV_STX_V2, 0x02, 0x00, 0x00, 0x00, 0x01, 0x01, 0x00, 0x00, 0x00,
+        ]);
+        decoder.push(&v2_frame(3));
+
+        let frames = decode_all(&mut decoder).await;
+        assert_eq!(frames.len(), 1);
+        assert_eq!(frames[0].sequence(), 3);
+        assert!(decoder.take_parse_errors() > 0);
+        assert_eq!(decoder.bytes_discarded(), 10);
+    }
+
+    #[tokio::test]
+    async fn stx_inside_payload_is_kept() {
+        let message = heartbeat(
+            MavType::MAV_TYPE_QUADROTOR,
+            u32::from_le_bytes([0xFD, 0xFE, 0xFD, 0xFE]),
+        );
+        let frame = frame(MavlinkVersion::V2, 1, 1, 0, &message)
+            .raw_bytes()
+            .to_vec();
+
+        let mut decoder = StreamDecoder::new();
+        decoder.push(&frame);
+        decoder.push(&v1_frame(1));
+
+        let frames = decode_all(&mut decoder).await;
+        assert_eq!(frames.len(), 2);
+        assert_eq!(frames[0].raw_bytes(), frame.as_slice());
+        assert_eq!(decoder.bytes_discarded(), 0);
+    }
+
+    #[tokio::test]
+    async fn mixed_versions_and_signed_frames() {
+        let mut decoder = StreamDecoder::new();
+        decoder.push(&v1_frame(0));
+        decoder.push(&v2_frame(1));
+        let signed_frame = signed_v2_frame(2);
+        decoder.push(&signed_frame);
+        decoder.push(&v2_frame(3));
+
+        let frames = decode_all(&mut decoder).await;
+        let versions: Vec<_> = frames.iter().map(|frame| frame.version()).collect();
+        assert_eq!(
+            versions,
+            vec![
+                mavlink::MavlinkVersion::V1,
+                mavlink::MavlinkVersion::V2,
+                mavlink::MavlinkVersion::V2,
+                mavlink::MavlinkVersion::V2,
+            ]
+        );
+        let sequences: Vec<u8> = frames.iter().map(|frame| frame.sequence()).collect();
+        assert_eq!(sequences, vec![0, 1, 2, 3]);
+        // Signed frames are forwarded untouched, with their signature
+        assert_eq!(frames[2].raw_bytes(), signed_frame.as_slice());
+        assert_eq!(frames[2].system_id(), 1);
+        assert_eq!(decoder.bytes_discarded(), 0);
+    }
+}
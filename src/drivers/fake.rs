@@ -1,24 +1,50 @@
+use std::sync::Arc;
+
 use anyhow::Result;
 use mavlink::{ardupilotmega::MavMessage, read_v2_raw_message_async};
 use tokio::sync::broadcast;
 use tracing::*;
 
 use crate::{
-    drivers::{Driver, DriverInfo},
+    drivers::{
+        filter::Filter,
+        stats::{DriverStats, DriverStatsCounters},
+        Driver, DriverInfo,
+    },
     protocol::Protocol,
+    router::Router,
 };
 
-pub struct FakeSink;
+#[derive(Default)]
+pub struct FakeSink {
+    stats: Arc<DriverStatsCounters>,
+}
+
 pub struct FakeSource {
     pub period: std::time::Duration,
+    stats: Arc<DriverStatsCounters>,
+}
+
+impl FakeSource {
+    pub fn new(period: std::time::Duration) -> Self {
+        Self {
+            period,
+            stats: Arc::new(DriverStatsCounters::default()),
+        }
+    }
 }
 
 #[async_trait::async_trait]
 impl Driver for FakeSink {
-    async fn run(&self, hub_sender: broadcast::Sender<Protocol>) -> Result<()> {
+    async fn run(
+        &self,
+        hub_sender: broadcast::Sender<Protocol>,
+        _router: Arc<Router>,
+    ) -> Result<()> {
         let mut hub_receiver = hub_sender.subscribe();
 
         while let Ok(message) = hub_receiver.recv().await {
+            self.stats.record_sent(message.raw_bytes().len());
             trace!("Message received: {message:?}")
         }
 
@@ -28,13 +54,23 @@ impl Driver for FakeSink {
     fn info(&self) -> DriverInfo {
         DriverInfo {
             name: "FakeSink".to_string(),
+            endpoint: None,
+            filter: Filter::default(),
         }
     }
+
+    fn stats(&self) -> DriverStats {
+        self.stats.snapshot()
+    }
 }
 
 #[async_trait::async_trait]
 impl Driver for FakeSource {
-    async fn run(&self, hub_sender: broadcast::Sender<Protocol>) -> Result<()> {
+    async fn run(
+        &self,
+        hub_sender: broadcast::Sender<Protocol>,
+        router: Arc<Router>,
+    ) -> Result<()> {
         let mut sequence = 0;
 
         let mut buf: Vec<u8> = Vec::with_capacity(280);
@@ -67,9 +103,11 @@ impl Driver for FakeSource {
 
             trace!("Fake message created: {message:?}");
 
+            self.stats.record_received(message.raw_bytes().len());
+
             let message = Protocol::new("", message);
 
-            hub_sender.send(message).unwrap();
+            router.send_to_hub(&hub_sender, message).unwrap();
 
             tokio::time::sleep(self.period).await;
         }
@@ -78,6 +116,12 @@ impl Driver for FakeSource {
     fn info(&self) -> DriverInfo {
         DriverInfo {
             name: "FakeSource".to_string(),
+            endpoint: None,
+            filter: Filter::default(),
         }
     }
+
+    fn stats(&self) -> DriverStats {
+        self.stats.snapshot()
+    }
 }
@@ -0,0 +1,102 @@
+use std::collections::HashSet;
+
+use anyhow::{anyhow, Context, Result};
+use mavlink::{ardupilotmega::MavMessage, Message};
+use serde::Serialize;
+
+use crate::protocol::MAVLinkMessageRaw;
+
+/// Allow and block lists for one direction of a link.
+/// Empty allow lists allow everything, block lists take precedence over allow lists.
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct FilterRules {
+    pub allow_msgid: HashSet<u32>,
+    pub block_msgid: HashSet<u32>,
+    pub allow_sysid: HashSet<u8>,
+    pub block_sysid: HashSet<u8>,
+    pub allow_compid: HashSet<u8>,
+    pub block_compid: HashSet<u8>,
+}
+
+impl FilterRules {
+    pub fn accepts(&self, message: &MAVLinkMessageRaw) -> bool {
+        fn check<T: Eq + std::hash::Hash>(allow: &HashSet<T>, block: &HashSet<T>, id: T) -> bool {
+            !block.contains(&id) && (allow.is_empty() || allow.contains(&id))
+        }
+
+        check(&self.allow_msgid, &self.block_msgid, message.message_id())
+            && check(&self.allow_sysid, &self.block_sysid, message.system_id())
+            && check(
+                &self.allow_compid,
+                &self.block_compid,
+                message.component_id(),
+            )
+    }
+}
+
+/// Message filter of a driver: `incoming` applies to messages received from the link before they
+/// reach the hub, `outgoing` to messages from the hub before they are sent to the link.
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct Filter {
+    pub incoming: FilterRules,
+    pub outgoing: FilterRules,
+}
+
+impl Filter {
+    pub fn accepts_incoming(&self, message: &MAVLinkMessageRaw) -> bool {
+        self.incoming.accepts(message)
+    }
+
+    pub fn accepts_outgoing(&self, message: &MAVLinkMessageRaw) -> bool {
+        self.outgoing.accepts(message)
+    }
+
+    /// Parses filter rules from the endpoint query, e.g.:
+    /// `out_block_msgid=ATTITUDE,30&in_allow_sysid=1`.
+    /// Rules without the `in_` or `out_` prefix apply to both directions.
+    pub fn from_query(query: &str) -> Result<Self> {
+        let mut filter = Self::default();
+
+        for rule in query.split('&').filter(|rule| !rule.is_empty()) {
+            let (key, values) = rule.split_once('=').context(format!(
+                "Filter rule {rule:?} should be in the key=value format"
+            ))?;
+
+            let (directions, key) = match key.split_once('_') {
+                Some(("in", key)) => (vec![&mut filter.incoming], key),
+                Some(("out", key)) => (vec![&mut filter.outgoing], key),
+                _ => (vec![&mut filter.incoming, &mut filter.outgoing], key),
+            };
+
+            for rules in directions {
+                for value in values.split(',').filter(|value| !value.is_empty()) {
+                    match key {
+                        "allow_msgid" => rules.allow_msgid.insert(parse_msgid(value)?),
+                        "block_msgid" => rules.block_msgid.insert(parse_msgid(value)?),
+                        "allow_sysid" => rules.allow_sysid.insert(parse_id(value)?),
+                        "block_sysid" => rules.block_sysid.insert(parse_id(value)?),
+                        "allow_compid" => rules.allow_compid.insert(parse_id(value)?),
+                        "block_compid" => rules.block_compid.insert(parse_id(value)?),
+                        _ => return Err(anyhow!("Unknown filter rule: {key:?}")),
+                    };
+                }
+            }
+        }
+
+        Ok(filter)
+    }
+}
+
+/// Message ids can be given by number or by name, e.g.: `30` or `ATTITUDE`
+fn parse_msgid(value: &str) -> Result<u32> {
+    value
+        .parse::<u32>()
+        .or_else(|_| MavMessage::message_id_from_name(&value.to_uppercase()))
+        .map_err(|_| anyhow!("Unknown message id: {value:?}"))
+}
+
+fn parse_id(value: &str) -> Result<u8> {
+    value
+        .parse::<u8>()
+        .context(format!("Invalid id: {value:?}"))
+}
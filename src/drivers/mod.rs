@@ -1,18 +1,265 @@
+pub mod clients;
+pub mod decoder;
 pub mod fake;
+pub mod filter;
+pub mod serial;
+pub mod stats;
 pub mod tcp;
+pub mod tlog;
 pub mod udp;
+pub mod unix;
+pub mod websocket;
 
-use crate::protocol::Protocol;
-use anyhow::Result;
+use std::{collections::HashMap, str::FromStr, sync::Arc, time::Duration};
+
+use crate::{cli, drivers::stats::DriverStats, protocol::Protocol, router::Router};
+use anyhow::{anyhow, Context, Result};
+use serde::Serialize;
 use tokio::sync::broadcast;
 
 #[async_trait::async_trait]
 pub trait Driver: Send + Sync {
-    async fn run(&self, hub_sender: broadcast::Sender<Protocol>) -> Result<()>;
+    /// Runs the driver, sending what it receives to the hub through its router, which also
+    /// decides which of the hub messages go to each link
+    async fn run(&self, hub_sender: broadcast::Sender<Protocol>, router: Arc<Router>)
+        -> Result<()>;
     fn info(&self) -> DriverInfo;
+    fn stats(&self) -> DriverStats;
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize)]
 pub struct DriverInfo {
-    name: String,
+    pub name: String,
+    /// Endpoint the driver was created from, filled by the hub
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub endpoint: Option<String>,
+    pub filter: filter::Filter,
+}
+
+/// Creates a driver from an endpoint in the same format accepted by the CLI, e.g.:
+/// "tcpc:127.0.0.1:5760?out_block_msgid=ATTITUDE"
+pub fn create_driver(endpoint: &str) -> Result<Arc<dyn Driver>> {
+    let endpoint = cli::endpoints_parser(endpoint).map_err(|error| anyhow!(error))?;
+    let (endpoint, query) = endpoint.split_once('?').unwrap_or((&endpoint, ""));
+    let (kind, address) = endpoint
+        .split_once(':')
+        .context("Endpoint should start with its kind")?;
+
+    let (options, filter) = parse_query(kind, query)?;
+
+    let driver: Arc<dyn Driver> = match kind {
+        "tcpc" => Arc::new(tcp::client::TcpClient::new(address, filter)),
+        "tcps" => Arc::new(tcp::server::TcpServer::new(address, filter)),
+        "udpc" => Arc::new(udp::client::UdpClient::new(address, filter)),
+        "udps" => Arc::new(udp::server::UdpServer::new(
+            address,
+            filter,
+            client_timeout(&options)?,
+        )),
+        "udpb" => Arc::new(udp::broadcast::UdpBroadcast::new(address, filter)),
+        "udpm" => Arc::new(udp::multicast::UdpMulticast::new(
+            address,
+            filter,
+            multicast_ttl(&options)?,
+            flag(&options, "loop")?,
+        )),
+        "ws" => Arc::new(websocket::server::WebSocketServer::new(
+            address,
+            filter,
+            websocket::WebSocketEncoding::Raw,
+        )),
+        "wsjson" => Arc::new(websocket::server::WebSocketServer::new(
+            address,
+            filter,
+            websocket::WebSocketEncoding::Json,
+        )),
+        "unixs" => Arc::new(unix::server::UnixServer::new(
+            address,
+            filter,
+            socket_mode(&options)?,
+        )),
+        "unixc" => Arc::new(unix::client::UnixClient::new(address, filter)),
+        "unixds" => Arc::new(unix::datagram::UnixDatagramServer::new(
+            address,
+            filter,
+            socket_mode(&options)?,
+            client_timeout(&options)?,
+        )),
+        "unixdc" => Arc::new(unix::datagram::UnixDatagramClient::new(address, filter)),
+        "serial" => {
+            let (port_name, baud_rate) = address
+                .rsplit_once(':')
+                .context("Serial endpoint should have a baud rate")?;
+            let baud_rate = baud_rate.parse::<u32>()?;
+
+            Arc::new(serial::Serial::new(port_name, baud_rate, filter))
+        }
+        "tlogw" => Arc::new(tlog::writer::TlogWriter::new(
+            address,
+            filter,
+            max_file_size(&options)?,
+            max_file_duration(&options)?,
+        )),
+        "tlogr" => Arc::new(tlog::reader::TlogReader::new(
+            address,
+            filter,
+            replay_speed(&options)?,
+            flag(&options, "loop")?,
+            replay_offset(&options, "start")?,
+            replay_offset(&options, "end")?,
+        )),
+        _ => return Err(anyhow!("Unknown kind: {kind:?} for endpoint")),
+    };
+
+    Ok(driver)
+}
+
+/// Splits the endpoint query into the options of the driver of that kind, e.g.:
+/// "client_timeout=30", and its filter rules, validating both
+pub fn parse_query<'a>(
+    kind: &str,
+    query: &'a str,
+) -> Result<(HashMap<&'a str, &'a str>, filter::Filter)> {
+    let option_keys: &[&str] = match kind {
+        "udps" => &["client_timeout"],
+        "udpm" => &["ttl", "loop"],
+        "unixs" => &["mode"],
+        "unixds" => &["mode", "client_timeout"],
+        "tlogw" => &["max_size", "max_duration"],
+        "tlogr" => &["speed", "loop", "start", "end"],
+        _ => &[],
+    };
+
+    let (options, rules) = take_options(query, option_keys);
+    client_timeout(&options)?;
+    socket_mode(&options)?;
+    multicast_ttl(&options)?;
+    max_file_size(&options)?;
+    max_file_duration(&options)?;
+    replay_speed(&options)?;
+    flag(&options, "loop")?;
+    replay_offset(&options, "start")?;
+    replay_offset(&options, "end")?;
+
+    Ok((options, filter::Filter::from_query(&rules)?))
+}
+
+/// Takes the driver options out of the endpoint query, leaving the filter rules
+fn take_options<'a>(query: &'a str, keys: &[&str]) -> (HashMap<&'a str, &'a str>, String) {
+    let mut options = HashMap::new();
+    let mut rules = vec![];
+
+    for pair in query.split('&').filter(|pair| !pair.is_empty()) {
+        match pair.split_once('=') {
+            Some((key, value)) if keys.contains(&key) => {
+                options.insert(key, value);
+            }
+            _ => rules.push(pair),
+        }
+    }
+
+    (options, rules.join("&"))
+}
+
+/// Parses the option with the given key, `None` if the endpoint doesn't set it
+fn parse_option<T: FromStr>(
+    options: &HashMap<&str, &str>,
+    key: &str,
+    expected: &str,
+    is_valid: impl Fn(&T) -> bool,
+) -> Result<Option<T>> {
+    let Some(&value) = options.get(key) else {
+        return Ok(None);
+    };
+
+    match value.parse::<T>() {
+        Ok(parsed) if is_valid(&parsed) => Ok(Some(parsed)),
+        _ => Err(anyhow!("Invalid {key}: {value:?}, it should be {expected}")),
+    }
+}
+
+/// Boolean option, e.g.: "loop=true", false if not set
+fn flag(options: &HashMap<&str, &str>, key: &str) -> Result<bool> {
+    Ok(parse_option(options, key, "true or false", |_: &bool| true)?.unwrap_or(false))
+}
+
+/// Seconds of silence after which a datagram client stops receiving messages
+fn client_timeout(options: &HashMap<&str, &str>) -> Result<Duration> {
+    let timeout = parse_option(
+        options,
+        "client_timeout",
+        "a positive number of seconds",
+        |seconds: &f64| *seconds > 0. && seconds.is_finite(),
+    )?;
+
+    Ok(timeout.map_or(clients::DEFAULT_CLIENT_TIMEOUT, Duration::from_secs_f64))
+}
+
+/// Time to live, or hop limit for IPv6, of the datagrams sent to multicast groups, e.g.: "ttl=4"
+fn multicast_ttl(options: &HashMap<&str, &str>) -> Result<u32> {
+    let ttl = parse_option(options, "ttl", "a number from 0 to 255", |ttl: &u32| {
+        *ttl <= 255
+    })?;
+
+    Ok(ttl.unwrap_or(1))
+}
+
+/// Maximum size of each telemetry log file, set in megabytes, e.g.: "max_size=100"
+fn max_file_size(options: &HashMap<&str, &str>) -> Result<Option<u64>> {
+    let megabytes = parse_option(
+        options,
+        "max_size",
+        "a positive number of megabytes",
+        |megabytes: &u64| *megabytes > 0,
+    )?;
+
+    Ok(megabytes.map(|megabytes| megabytes * 1024 * 1024))
+}
+
+/// Maximum duration of each telemetry log file, set in minutes, e.g.: "max_duration=60"
+fn max_file_duration(options: &HashMap<&str, &str>) -> Result<Option<Duration>> {
+    let minutes = parse_option(
+        options,
+        "max_duration",
+        "a positive number of minutes",
+        |minutes: &u64| *minutes > 0,
+    )?;
+
+    Ok(minutes.map(|minutes| Duration::from_secs(minutes * 60)))
+}
+
+/// Speed multiplier of the telemetry log replay, e.g.: "speed=2" replays twice as fast
+fn replay_speed(options: &HashMap<&str, &str>) -> Result<f64> {
+    let speed = parse_option(options, "speed", "a positive number", |speed: &f64| {
+        *speed > 0. && speed.is_finite()
+    })?;
+
+    Ok(speed.unwrap_or(1.))
+}
+
+/// Offset, in seconds from the start of the telemetry log, where the replay starts or stops, e.g.:
+/// "start=10"
+fn replay_offset(options: &HashMap<&str, &str>, key: &str) -> Result<Option<Duration>> {
+    let offset = parse_option(
+        options,
+        key,
+        "a positive number of seconds",
+        |seconds: &f64| *seconds >= 0. && seconds.is_finite(),
+    )?;
+
+    Ok(offset.map(Duration::from_secs_f64))
+}
+
+/// Permissions of the socket file, in octal, e.g.: "660", left to the process umask if not set
+fn socket_mode(options: &HashMap<&str, &str>) -> Result<Option<u32>> {
+    let Some(&mode) = options.get("mode") else {
+        return Ok(None);
+    };
+
+    match u32::from_str_radix(mode.trim_start_matches("0o"), 8) {
+        Ok(mode) if mode <= 0o7777 => Ok(Some(mode)),
+        _ => Err(anyhow!(
+            "Invalid mode: {mode:?}, it should be an octal number, e.g.: 660"
+        )),
+    }
 }
@@ -0,0 +1,259 @@
+use std::sync::Arc;
+
+use anyhow::Result;
+use tokio::{
+    io::{AsyncReadExt, AsyncWriteExt, ReadHalf, WriteHalf},
+    sync::broadcast,
+};
+use tokio_serial::{SerialPortBuilderExt, SerialStream};
+use tracing::*;
+
+use crate::{
+    drivers::{
+        decoder::StreamDecoder,
+        filter::Filter,
+        stats::{DriverStats, DriverStatsCounters},
+        Driver, DriverInfo,
+    },
+    protocol::Protocol,
+    router::Router,
+};
+
+pub struct Serial {
+    pub port_name: String,
+    pub baud_rate: u32,
+    pub filter: Filter,
+    stats: Arc<DriverStatsCounters>,
+}
+
+impl Serial {
+    #[instrument(level = "debug")]
+    pub fn new(port_name: &str, baud_rate: u32, filter: Filter) -> Self {
+        Self {
+            port_name: port_name.to_string(),
+            baud_rate,
+            filter,
+            stats: Arc::new(DriverStatsCounters::default()),
+        }
+    }
+
+    /// Receives messages from the Serial Port and sends them to the HUB Channel
+    #[instrument(level = "debug", skip(reader, hub_sender, router, filter, stats))]
+    async fn serial_receive_task(
+        mut reader: ReadHalf<SerialStream>,
+        port_name: &str,
+        hub_sender: Arc<broadcast::Sender<Protocol>>,
+        router: &Router,
+        filter: &Filter,
+        stats: &DriverStatsCounters,
+    ) -> Result<()> {
+        let mut buf = Vec::with_capacity(1024);
+        let mut decoder = StreamDecoder::new();
+
+        loop {
+            buf.clear();
+
+            let bytes_received = reader.read_buf(&mut buf).await?;
+
+            if bytes_received == 0 {
+                warn!("Serial port {port_name} was closed.");
+                break;
+            }
+
+            decoder.push(&buf[..bytes_received]);
+
+            while let Some(message) = decoder.next_frame().await {
+                stats.record_received(message.raw_bytes().len());
+
+                if !filter.accepts_incoming(&message) {
+                    continue;
+                }
+
+                let message = Protocol::new(port_name, message);
+
+                trace!("Received Serial message: {message:?}");
+                if let Err(error) = router.send_to_hub(&hub_sender, message) {
+                    error!("Failed to send message to hub: {error:?}");
+                }
+            }
+
+            stats.record_parse_errors(decoder.take_parse_errors());
+            stats.record_discarded(decoder.take_bytes_discarded());
+        }
+
+        debug!(
+            "Serial Receive task for {port_name} finished, {} bytes discarded",
+            decoder.bytes_discarded()
+        );
+        Ok(())
+    }
+
+    /// Receives messages from the HUB Channel and sends them to the Serial Port
+    #[instrument(level = "debug", skip(writer, hub_receiver, router, filter, stats))]
+    async fn serial_send_task(
+        mut writer: WriteHalf<SerialStream>,
+        port_name: &str,
+        mut hub_receiver: broadcast::Receiver<Protocol>,
+        router: &Router,
+        filter: &Filter,
+        stats: &DriverStatsCounters,
+    ) -> Result<()> {
+        loop {
+            let message = match hub_receiver.recv().await {
+                Ok(message) => message,
+                Err(broadcast::error::RecvError::Closed) => {
+                    error!("Hub channel closed!");
+                    break;
+                }
+                Err(broadcast::error::RecvError::Lagged(count)) => {
+                    warn!("Channel lagged by {count} messages.");
+                    stats.record_lagged(count);
+                    continue;
+                }
+            };
+
+            if message.origin.eq(&port_name) {
+                continue; // Don't do loopback
+            }
+
+            if !router.should_forward(&message, port_name) {
+                continue; // Targeted to another link
+            }
+
+            if !filter.accepts_outgoing(&message) {
+                continue;
+            }
+
+            writer.write_all(message.raw_bytes()).await?;
+            writer.flush().await?;
+            stats.record_sent(message.raw_bytes().len());
+
+            trace!("Message sent to {port_name} from Serial: {message:?}");
+        }
+
+        debug!("Serial Send task for {port_name} finished");
+        Ok(())
+    }
+}
+
+#[async_trait::async_trait]
+impl Driver for Serial {
+    #[instrument(level = "debug", skip(self, hub_sender, router))]
+    async fn run(
+        &self,
+        hub_sender: broadcast::Sender<Protocol>,
+        router: Arc<Router>,
+    ) -> Result<()> {
+        let port_name = &self.port_name;
+        let baud_rate = self.baud_rate;
+        let hub_sender = Arc::new(hub_sender);
+        let mut first_connection = true;
+
+        loop {
+            debug!("Trying to open Serial port {port_name:?} at {baud_rate} bauds...");
+            let port = match tokio_serial::new(port_name, baud_rate).open_native_async() {
+                Ok(port) => port,
+                Err(error) => {
+                    error!("Failed opening Serial port {port_name:?}: {error:?}");
+                    tokio::time::sleep(tokio::time::Duration::from_secs(1)).await;
+                    continue;
+                }
+            };
+            debug!("Serial port {port_name:?} successfully opened");
+
+            if !first_connection {
+                self.stats.record_reconnection();
+            }
+            first_connection = false;
+            self.stats.add_peer(port_name);
+
+            let (reader, writer) = tokio::io::split(port);
+
+            let hub_receiver = hub_sender.subscribe();
+            let hub_sender_cloned = Arc::clone(&hub_sender);
+
+            tokio::select! {
+                result = Serial::serial_receive_task(reader, port_name, hub_sender_cloned, &router, &self.filter, &self.stats) => {
+                    if let Err(e) = result {
+                        error!("Error in Serial receive task: {e:?}");
+                    }
+                }
+                result = Serial::serial_send_task(writer, port_name, hub_receiver, &router, &self.filter, &self.stats) => {
+                    if let Err(e) = result {
+                        error!("Error in Serial send task: {e:?}");
+                    }
+                }
+            }
+
+            self.stats.remove_peer(port_name);
+
+            debug!("Restarting Serial connection loop...");
+            tokio::time::sleep(tokio::time::Duration::from_secs(1)).await;
+        }
+    }
+
+    #[instrument(level = "debug", skip(self))]
+    fn info(&self) -> DriverInfo {
+        DriverInfo {
+            name: "Serial".to_string(),
+            endpoint: None,
+            filter: self.filter.clone(),
+        }
+    }
+
+    #[instrument(level = "debug", skip(self))]
+    fn stats(&self) -> DriverStats {
+        self.stats.snapshot()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use mavlink::{ardupilotmega::MavType, MavlinkVersion};
+    use tokio::time::{timeout, Duration};
+    use tokio_serial::SerialPort;
+
+    use super::*;
+    use crate::protocol::fixtures::{frame, heartbeat, heartbeat_frame};
+
+    const TIMEOUT: Duration = Duration::from_secs(5);
+
+    #[tokio::test]
+    async fn pseudo_terminal_round_trip() {
+        let (mut peer, port) = SerialStream::pair().unwrap();
+        // Opened again by the driver, so it is configured (e.g. in raw mode) like any serial port
+        let port_name = port.name().unwrap();
+        drop(port);
+
+        let serial = Serial::new(&port_name, 115200, Filter::default());
+        let (hub_sender, mut hub_receiver) = broadcast::channel::<Protocol>(16);
+
+        let round_trip = async {
+            while serial.stats().peers.is_empty() {
+                tokio::time::sleep(Duration::from_millis(10)).await;
+            }
+
+            // From the peer to the hub
+            let sent = heartbeat_frame(MavlinkVersion::V2, 0);
+            peer.write_all(&sent).await.unwrap();
+            let received = hub_receiver.recv().await.unwrap();
+            assert_eq!(received.origin, port_name);
+            assert_eq!(received.raw_bytes(), sent.as_slice());
+
+            // From the hub to the peer
+            let message = heartbeat(MavType::MAV_TYPE_GCS, 0);
+            let message = Protocol::new("other", frame(MavlinkVersion::V2, 255, 190, 0, &message));
+            hub_sender.send(message.clone()).unwrap();
+            let mut received = vec![0; message.raw_bytes().len()];
+            peer.read_exact(&mut received).await.unwrap();
+            assert_eq!(received, message.raw_bytes());
+        };
+
+        tokio::select! {
+            result = serial.run(hub_sender.clone(), Arc::new(Router::default())) => {
+                panic!("Serial driver finished: {result:?}");
+            }
+            result = timeout(TIMEOUT, round_trip) => result.unwrap(),
+        }
+    }
+}
@@ -0,0 +1,103 @@
+use std::{
+    collections::HashSet,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        RwLock,
+    },
+};
+
+use serde::Serialize;
+
+/// Snapshot of the statistics of a driver
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct DriverStats {
+    pub messages_received: u64,
+    pub bytes_received: u64,
+    pub messages_sent: u64,
+    pub bytes_sent: u64,
+    pub parse_errors: u64,
+    /// Bytes received from the link that were not part of a valid frame
+    pub bytes_discarded: u64,
+    /// Messages from the hub that were dropped because the driver couldn't keep up
+    pub lagged_messages: u64,
+    pub reconnections: u64,
+    /// UNIX timestamp in microseconds of the last message received from the link
+    pub last_message_time_us: Option<u64>,
+    pub peers: Vec<String>,
+}
+
+/// Live statistics of a driver, shared between its tasks
+#[derive(Debug, Default)]
+pub struct DriverStatsCounters {
+    messages_received: AtomicU64,
+    bytes_received: AtomicU64,
+    messages_sent: AtomicU64,
+    bytes_sent: AtomicU64,
+    parse_errors: AtomicU64,
+    bytes_discarded: AtomicU64,
+    lagged_messages: AtomicU64,
+    reconnections: AtomicU64,
+    last_message_time_us: AtomicU64,
+    peers: RwLock<HashSet<String>>,
+}
+
+impl DriverStatsCounters {
+    pub fn record_received(&self, bytes: usize) {
+        self.messages_received.fetch_add(1, Ordering::Relaxed);
+        self.bytes_received
+            .fetch_add(bytes as u64, Ordering::Relaxed);
+        self.last_message_time_us.store(
+            chrono::Utc::now().timestamp_micros() as u64,
+            Ordering::Relaxed,
+        );
+    }
+
+    pub fn record_sent(&self, bytes: usize) {
+        self.messages_sent.fetch_add(1, Ordering::Relaxed);
+        self.bytes_sent.fetch_add(bytes as u64, Ordering::Relaxed);
+    }
+
+    pub fn record_parse_errors(&self, count: u64) {
+        self.parse_errors.fetch_add(count, Ordering::Relaxed);
+    }
+
+    pub fn record_discarded(&self, bytes: u64) {
+        self.bytes_discarded.fetch_add(bytes, Ordering::Relaxed);
+    }
+
+    pub fn record_lagged(&self, count: u64) {
+        self.lagged_messages.fetch_add(count, Ordering::Relaxed);
+    }
+
+    pub fn record_reconnection(&self) {
+        self.reconnections.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn add_peer(&self, peer: &str) {
+        self.peers.write().unwrap().insert(peer.to_string());
+    }
+
+    pub fn remove_peer(&self, peer: &str) {
+        self.peers.write().unwrap().remove(peer);
+    }
+
+    pub fn snapshot(&self) -> DriverStats {
+        let last_message_time_us = self.last_message_time_us.load(Ordering::Relaxed);
+
+        let mut peers: Vec<String> = self.peers.read().unwrap().iter().cloned().collect();
+        peers.sort();
+
+        DriverStats {
+            messages_received: self.messages_received.load(Ordering::Relaxed),
+            bytes_received: self.bytes_received.load(Ordering::Relaxed),
+            messages_sent: self.messages_sent.load(Ordering::Relaxed),
+            bytes_sent: self.bytes_sent.load(Ordering::Relaxed),
+            parse_errors: self.parse_errors.load(Ordering::Relaxed),
+            bytes_discarded: self.bytes_discarded.load(Ordering::Relaxed),
+            lagged_messages: self.lagged_messages.load(Ordering::Relaxed),
+            reconnections: self.reconnections.load(Ordering::Relaxed),
+            last_message_time_us: (last_message_time_us != 0).then_some(last_message_time_us),
+            peers,
+        }
+    }
+}
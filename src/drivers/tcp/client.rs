@@ -1,38 +1,52 @@
 use std::sync::Arc;
 
 use crate::drivers::tcp::{tcp_receive_task, tcp_send_task};
-use crate::protocol::Protocol;
+use crate::{protocol::Protocol, router::Router};
 use anyhow::Result;
 use tokio::net::TcpStream;
-use tokio::sync::{broadcast, Mutex};
+use tokio::sync::broadcast;
 use tracing::*;
 
-use crate::drivers::{Driver, DriverInfo};
+use crate::drivers::{
+    filter::Filter,
+    stats::{DriverStats, DriverStatsCounters},
+    Driver, DriverInfo,
+};
 
 pub struct TcpClient {
     pub remote_addr: String,
+    pub filter: Filter,
+    stats: Arc<DriverStatsCounters>,
 }
 
 impl TcpClient {
     #[instrument(level = "debug")]
-    pub fn new(remote_addr: &str) -> Self {
+    pub fn new(remote_addr: &str, filter: Filter) -> Self {
         Self {
             remote_addr: remote_addr.to_string(),
+            filter,
+            stats: Arc::new(DriverStatsCounters::default()),
         }
     }
 }
 
 #[async_trait::async_trait]
 impl Driver for TcpClient {
-    #[instrument(level = "debug", skip(self, hub_sender))]
-    async fn run(&self, hub_sender: broadcast::Sender<Protocol>) -> Result<()> {
+    #[instrument(level = "debug", skip(self, hub_sender, router))]
+    async fn run(
+        &self,
+        hub_sender: broadcast::Sender<Protocol>,
+        router: Arc<Router>,
+    ) -> Result<()> {
         let server_addr = &self.remote_addr;
         let hub_sender = Arc::new(hub_sender);
 
+        let mut first_connection = true;
+
         loop {
             debug!("Trying to connect to {server_addr:?}...");
             let socket = match TcpStream::connect(server_addr).await {
-                Ok(socket) => Arc::new(Mutex::new(socket)),
+                Ok(socket) => socket,
                 Err(error) => {
                     error!("Failed connecting to {server_addr:?}: {error:?}");
                     tokio::time::sleep(tokio::time::Duration::from_secs(1)).await;
@@ -41,22 +55,32 @@ impl Driver for TcpClient {
             };
             debug!("TcpClient successfully connected to {server_addr:?}");
 
+            if !first_connection {
+                self.stats.record_reconnection();
+            }
+            first_connection = false;
+            self.stats.add_peer(server_addr);
+
+            // Each task owns its half, so sending never waits on a pending read
+            let (reader, writer) = tokio::io::split(socket);
             let hub_receiver = hub_sender.subscribe();
             let hub_sender_cloned = Arc::clone(&hub_sender);
 
             tokio::select! {
-                result = tcp_receive_task(socket.clone(), server_addr, hub_sender_cloned) => {
+                result = tcp_receive_task(reader, server_addr, hub_sender_cloned, &router, &self.filter, &self.stats) => {
                     if let Err(e) = result {
                         error!("Error in TCP receive task: {e:?}");
                     }
                 }
-                result = tcp_send_task(socket, server_addr, hub_receiver) => {
+                result = tcp_send_task(writer, server_addr, hub_receiver, &router, &self.filter, &self.stats) => {
                     if let Err(e) = result {
                         error!("Error in TCP send task: {e:?}");
                     }
                 }
             }
 
+            self.stats.remove_peer(server_addr);
+
             debug!("Restarting TCP Client connection loop...");
         }
     }
@@ -65,6 +89,13 @@ impl Driver for TcpClient {
     fn info(&self) -> DriverInfo {
         DriverInfo {
             name: "TcpClient".to_string(),
+            endpoint: None,
+            filter: self.filter.clone(),
         }
     }
+
+    #[instrument(level = "debug", skip(self))]
+    fn stats(&self) -> DriverStats {
+        self.stats.snapshot()
+    }
 }
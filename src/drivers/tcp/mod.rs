@@ -1,68 +1,83 @@
 use std::sync::Arc;
 
 use anyhow::Result;
-use mavlink::ardupilotmega::MavMessage;
 use tokio::{
-    io::{AsyncReadExt, AsyncWriteExt},
-    net::TcpStream,
-    sync::{broadcast, Mutex},
+    io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt},
+    sync::broadcast,
 };
 use tracing::*;
 
-use crate::protocol::Protocol;
+use crate::{
+    drivers::{decoder::StreamDecoder, filter::Filter, stats::DriverStatsCounters},
+    protocol::Protocol,
+    router::Router,
+};
 
 pub mod client;
 pub mod server;
 
-/// Receives messages from the TCP Socket and sends them to the HUB Channel
-#[instrument(level = "debug", skip(socket, hub_sender))]
-async fn tcp_receive_task(
-    socket: Arc<Mutex<TcpStream>>,
+/// Receives messages from the read half of the TCP Socket, or any other byte stream (e.g. a Unix
+/// socket), and sends them to the HUB Channel
+#[instrument(level = "debug", skip(reader, hub_sender, router, filter, stats))]
+pub(crate) async fn tcp_receive_task<R: AsyncRead + Unpin>(
+    mut reader: R,
     remote_addr: &str,
     hub_sender: Arc<broadcast::Sender<Protocol>>,
+    router: &Router,
+    filter: &Filter,
+    stats: &DriverStatsCounters,
 ) -> Result<()> {
     let mut buf = Vec::with_capacity(1024);
+    let mut decoder = StreamDecoder::new();
 
     loop {
         buf.clear();
 
-        let bytes_received = socket.lock().await.read_buf(&mut buf).await?;
+        let bytes_received = reader.read_buf(&mut buf).await?;
 
         if bytes_received == 0 {
             warn!("TCP connection closed by {remote_addr}.");
             break;
         }
 
-        let message = match mavlink::read_v2_raw_message_async::<MavMessage, _>(
-            &mut (&buf[..bytes_received]),
-        )
-        .await
-        {
-            Ok(message) => message,
-            Err(error) => {
-                error!("Failed to parse MAVLink message: {error:?}");
-                continue; // Skip this iteration on error
+        decoder.push(&buf[..bytes_received]);
+
+        while let Some(message) = decoder.next_frame().await {
+            stats.record_received(message.raw_bytes().len());
+
+            if !filter.accepts_incoming(&message) {
+                continue;
             }
-        };
 
-        let message = Protocol::new(&remote_addr, message);
+            let message = Protocol::new(remote_addr, message);
 
-        trace!("Received TCP message: {message:?}");
-        if let Err(error) = hub_sender.send(message) {
-            error!("Failed to send message to hub: {error:?}");
+            trace!("Received TCP message: {message:?}");
+            if let Err(error) = router.send_to_hub(&hub_sender, message) {
+                error!("Failed to send message to hub: {error:?}");
+            }
         }
+
+        stats.record_parse_errors(decoder.take_parse_errors());
+        stats.record_discarded(decoder.take_bytes_discarded());
     }
 
-    debug!("TCP Receive task for {remote_addr} finished");
+    debug!(
+        "TCP Receive task for {remote_addr} finished, {} bytes discarded",
+        decoder.bytes_discarded()
+    );
     Ok(())
 }
 
-/// Receives messages from the HUB Channel and sends them to the TCP Socket
-#[instrument(level = "debug", skip(socket, hub_receiver))]
-async fn tcp_send_task(
-    socket: Arc<Mutex<TcpStream>>,
+/// Receives messages from the HUB Channel and sends them to the write half of the TCP Socket, or
+/// any other byte stream
+#[instrument(level = "debug", skip(writer, hub_receiver, router, filter, stats))]
+pub(crate) async fn tcp_send_task<W: AsyncWrite + Unpin>(
+    mut writer: W,
     remote_addr: &str,
     mut hub_receiver: broadcast::Receiver<Protocol>,
+    router: &Router,
+    filter: &Filter,
+    stats: &DriverStatsCounters,
 ) -> Result<()> {
     loop {
         let message = match hub_receiver.recv().await {
@@ -73,6 +88,7 @@ async fn tcp_send_task(
             }
             Err(broadcast::error::RecvError::Lagged(count)) => {
                 warn!("Channel lagged by {count} messages.");
+                stats.record_lagged(count);
                 continue;
             }
         };
@@ -81,7 +97,16 @@ async fn tcp_send_task(
             continue; // Don't do loopback
         }
 
-        socket.lock().await.write_all(message.raw_bytes()).await?;
+        if !router.should_forward(&message, remote_addr) {
+            continue; // Targeted to another link
+        }
+
+        if !filter.accepts_outgoing(&message) {
+            continue;
+        }
+
+        writer.write_all(message.raw_bytes()).await?;
+        stats.record_sent(message.raw_bytes().len());
 
         trace!("Message sent to {remote_addr} from TCP server: {message:?}");
     }
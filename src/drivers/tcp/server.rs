@@ -1,48 +1,65 @@
 use std::sync::Arc;
 
 use crate::drivers::tcp::{tcp_receive_task, tcp_send_task};
-use crate::protocol::Protocol;
+use crate::{protocol::Protocol, router::Router};
 use anyhow::Result;
 use tokio::net::{TcpListener, TcpStream};
-use tokio::sync::{broadcast, Mutex};
+use tokio::sync::broadcast;
+use tokio::task::JoinSet;
 use tracing::*;
 
-use crate::drivers::{Driver, DriverInfo};
+use crate::drivers::{
+    filter::Filter,
+    stats::{DriverStats, DriverStatsCounters},
+    Driver, DriverInfo,
+};
 
 pub struct TcpServer {
     pub local_addr: String,
+    pub filter: Filter,
+    stats: Arc<DriverStatsCounters>,
 }
 
 impl TcpServer {
     #[instrument(level = "debug")]
-    pub fn new(local_addr: &str) -> Self {
+    pub fn new(local_addr: &str, filter: Filter) -> Self {
         Self {
             local_addr: local_addr.to_string(),
+            filter,
+            stats: Arc::new(DriverStatsCounters::default()),
         }
     }
 
     /// Handles communication with a single client
-    #[instrument(level = "debug", skip(socket, hub_sender))]
+    #[instrument(level = "debug", skip(socket, hub_sender, router, filter, stats))]
     async fn handle_client(
-        socket: Arc<Mutex<TcpStream>>,
+        socket: TcpStream,
         remote_addr: String,
         hub_sender: Arc<broadcast::Sender<Protocol>>,
+        router: Arc<Router>,
+        filter: Filter,
+        stats: Arc<DriverStatsCounters>,
     ) -> Result<()> {
+        // Each task owns its half, so sending never waits on a pending read
+        let (reader, writer) = tokio::io::split(socket);
         let hub_receiver = hub_sender.subscribe();
+        stats.add_peer(&remote_addr);
 
         tokio::select! {
-            result = tcp_receive_task(socket.clone(), &remote_addr, hub_sender) => {
+            result = tcp_receive_task(reader, &remote_addr, hub_sender, &router, &filter, &stats) => {
                 if let Err(e) = result {
                     error!("Error in TCP receive task for {remote_addr}: {e:?}");
                 }
             }
-            result = tcp_send_task(socket, &remote_addr, hub_receiver) => {
+            result = tcp_send_task(writer, &remote_addr, hub_receiver, &router, &filter, &stats) => {
                 if let Err(e) = result {
                     error!("Error in TCP send task for {remote_addr}: {e:?}");
                 }
             }
         }
 
+        stats.remove_peer(&remote_addr);
+
         debug!("Finished handling connection with {remote_addr}");
         Ok(())
     }
@@ -50,22 +67,34 @@ impl TcpServer {
 
 #[async_trait::async_trait]
 impl Driver for TcpServer {
-    #[instrument(level = "debug", skip(self, hub_sender))]
-    async fn run(&self, hub_sender: broadcast::Sender<Protocol>) -> Result<()> {
+    #[instrument(level = "debug", skip(self, hub_sender, router))]
+    async fn run(
+        &self,
+        hub_sender: broadcast::Sender<Protocol>,
+        router: Arc<Router>,
+    ) -> Result<()> {
         let listener = TcpListener::bind(&self.local_addr).await?;
         let hub_sender = Arc::new(hub_sender);
 
+        // Client tasks are aborted when this set is dropped, so they don't outlive the driver
+        let mut clients = JoinSet::new();
+
         loop {
+            // Clean up finished clients
+            while clients.try_join_next().is_some() {}
+
             match listener.accept().await {
                 Ok((socket, remote_addr)) => {
                     let remote_addr = remote_addr.to_string();
                     let hub_sender_cloned = Arc::clone(&hub_sender);
-                    let socket = Arc::new(Mutex::new(socket));
 
-                    tokio::spawn(TcpServer::handle_client(
+                    clients.spawn(TcpServer::handle_client(
                         socket,
                         remote_addr,
                         hub_sender_cloned,
+                        router.clone(),
+                        self.filter.clone(),
+                        self.stats.clone(),
                     ));
                 }
                 Err(error) => {
@@ -80,6 +109,13 @@ impl Driver for TcpServer {
     fn info(&self) -> DriverInfo {
         DriverInfo {
             name: "TcpServer".to_string(),
+            endpoint: None,
+            filter: self.filter.clone(),
         }
     }
+
+    #[instrument(level = "debug", skip(self))]
+    fn stats(&self) -> DriverStats {
+        self.stats.snapshot()
+    }
 }
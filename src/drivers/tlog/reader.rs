@@ -0,0 +1,334 @@
+use std::{path::PathBuf, sync::Arc};
+
+use anyhow::{anyhow, Result};
+use tokio::{
+    fs::File,
+    io::{AsyncReadExt, BufReader},
+    sync::broadcast,
+    time::{Duration, Instant},
+};
+use tracing::*;
+
+use crate::{
+    drivers::{
+        decoder::{self, StreamDecoder},
+        filter::Filter,
+        stats::{DriverStats, DriverStatsCounters},
+        Driver, DriverInfo,
+    },
+    protocol::{MAVLinkMessageRaw, Protocol},
+    router::Router,
+};
+
+/// Size of the timestamp that precedes each frame in a record
+const TIMESTAMP_SIZE: usize = std::mem::size_of::<u64>();
+
+/// Replays a `.tlog` file into the hub as if it came from a live vehicle, honoring the original
+/// inter-message timing
+pub struct TlogReader {
+    pub path: PathBuf,
+    pub filter: Filter,
+    /// Replay speed multiplier, e.g.: 2.0 replays twice as fast
+    pub speed: f64,
+    /// Restarts from the beginning when the end is reached
+    pub looping: bool,
+    /// Skips messages before this offset from the start of the log
+    pub start: Option<Duration>,
+    /// Stops at this offset from the start of the log
+    pub end: Option<Duration>,
+    stats: Arc<DriverStatsCounters>,
+}
+
+impl TlogReader {
+    #[instrument(level = "debug")]
+    pub fn new(
+        path: &str,
+        filter: Filter,
+        speed: f64,
+        looping: bool,
+        start: Option<Duration>,
+        end: Option<Duration>,
+    ) -> Self {
+        Self {
+            path: PathBuf::from(path),
+            filter,
+            speed,
+            looping,
+            start,
+            end,
+            stats: Arc::new(DriverStatsCounters::default()),
+        }
+    }
+
+    /// Replays the whole file once, returning the amount of messages replayed
+    #[instrument(level = "debug", skip(self, hub_sender, router))]
+    async fn replay(
+        &self,
+        hub_sender: &broadcast::Sender<Protocol>,
+        router: &Router,
+    ) -> Result<usize> {
+        let origin = self.path.to_string_lossy().to_string();
+        let mut records = TlogRecords::new(File::open(&self.path).await?);
+        let mut messages_replayed = 0;
+
+        let replay_start = Instant::now();
+        let mut first_timestamp = None;
+
+        while let Some((timestamp, message)) = records.next_record().await? {
+            self.stats.record_discarded(records.take_bytes_discarded());
+
+            let first_timestamp = *first_timestamp.get_or_insert(timestamp);
+            let offset = Duration::from_micros(timestamp.saturating_sub(first_timestamp));
+
+            if self.start.is_some_and(|start| offset < start) {
+                continue;
+            }
+            if self.end.is_some_and(|end| offset > end) {
+                break;
+            }
+
+            self.stats.record_received(message.raw_bytes().len());
+
+            if !self.filter.accepts_incoming(&message) {
+                continue;
+            }
+
+            let replay_offset = offset
+                .saturating_sub(self.start.unwrap_or_default())
+                .div_f64(self.speed);
+            tokio::time::sleep_until(replay_start + replay_offset).await;
+
+            let message = Protocol::new(&origin, message);
+
+            trace!("Replaying tlog message: {message:?}");
+            if let Err(error) = router.send_to_hub(hub_sender, message) {
+                error!("Failed to send message to hub: {error:?}");
+            }
+            messages_replayed += 1;
+        }
+
+        self.stats.record_discarded(records.take_bytes_discarded());
+        if records.bytes_discarded > 0 {
+            warn!(
+                "Skipped {} corrupted bytes from tlog {:?}",
+                records.bytes_discarded, self.path
+            );
+        }
+
+        Ok(messages_replayed)
+    }
+}
+
+/// Splits a `.tlog` file into (timestamp, frame) records.
+///
+/// Like the [`StreamDecoder`], corrupted bytes are skipped one at a time until a valid frame is
+/// found, and a record cut off by the end of the file (e.g. the recorder was killed while writing
+/// it) is skipped the same way.
+struct TlogRecords {
+    reader: BufReader<File>,
+    buffer: Vec<u8>,
+    bytes_discarded: u64,
+    new_bytes_discarded: u64,
+}
+
+impl TlogRecords {
+    fn new(file: File) -> Self {
+        Self {
+            reader: BufReader::new(file),
+            buffer: Vec::with_capacity(1024),
+            bytes_discarded: 0,
+            new_bytes_discarded: 0,
+        }
+    }
+
+    /// Amount of bytes discarded since the last call
+    fn take_bytes_discarded(&mut self) -> u64 {
+        std::mem::take(&mut self.new_bytes_discarded)
+    }
+
+    /// Reads until the buffer has at least `size` bytes, returning `false` at the end of the file
+    async fn fill(&mut self, size: usize) -> Result<bool> {
+        while self.buffer.len() < size {
+            if self.reader.read_buf(&mut self.buffer).await? == 0 {
+                return Ok(false);
+            }
+        }
+
+        Ok(true)
+    }
+
+    /// Reads the next record, or `None` at the end of the file
+    async fn next_record(&mut self) -> Result<Option<(u64, MAVLinkMessageRaw)>> {
+        loop {
+            if !self.fill(TIMESTAMP_SIZE + 1).await? {
+                // Not even a timestamp and an STX left
+                let remaining = self.buffer.len();
+                self.discard(remaining);
+                return Ok(None);
+            }
+
+            let Some(header_size) = decoder::header_size(self.buffer[TIMESTAMP_SIZE]) else {
+                self.discard(1);
+                continue;
+            };
+
+            // The file can end in the middle of a record, or the STX can be a false one, which
+            // shouldn't hide the valid records after it
+            if !self.fill(TIMESTAMP_SIZE + header_size).await? {
+                trace!("Incomplete tlog record at the end of the file, resynchronizing...");
+                self.discard(1);
+                continue;
+            }
+
+            let frame_size =
+                decoder::frame_size(&self.buffer[TIMESTAMP_SIZE..]).expect("Header is complete");
+            let record_size = TIMESTAMP_SIZE + frame_size;
+
+            if !self.fill(record_size).await? {
+                trace!("Incomplete tlog record at the end of the file, resynchronizing...");
+                self.discard(1);
+                continue;
+            }
+
+            let mut decoder = StreamDecoder::new();
+            decoder.push(&self.buffer[TIMESTAMP_SIZE..record_size]);
+            match decoder.next_frame().await {
+                // The frame should start right after the timestamp, not somewhere inside it
+                Some(message) if decoder.take_bytes_discarded() == 0 => {
+                    let timestamp = u64::from_be_bytes(
+                        self.buffer[..TIMESTAMP_SIZE]
+                            .try_into()
+                            .expect("Timestamp is complete"),
+                    );
+                    self.buffer.drain(..record_size);
+
+                    return Ok(Some((timestamp, message)));
+                }
+                _ => {
+                    trace!("Invalid tlog record, resynchronizing...");
+                    self.discard(1);
+                }
+            }
+        }
+    }
+
+    fn discard(&mut self, amount: usize) {
+        self.buffer.drain(..amount);
+        self.bytes_discarded += amount as u64;
+        self.new_bytes_discarded += amount as u64;
+    }
+}
+
+#[async_trait::async_trait]
+impl Driver for TlogReader {
+    #[instrument(level = "debug", skip(self, hub_sender, router))]
+    async fn run(
+        &self,
+        hub_sender: broadcast::Sender<Protocol>,
+        router: Arc<Router>,
+    ) -> Result<()> {
+        loop {
+            info!("Replaying telemetry log {:?}", self.path);
+            if self.replay(&hub_sender, &router).await? == 0 {
+                return Err(anyhow!("No messages to replay from {:?}", self.path));
+            }
+
+            if !self.looping {
+                break;
+            }
+
+            // Looping isn't a reconnection, so it isn't counted as one
+            debug!("Looping telemetry log {:?}", self.path);
+        }
+
+        debug!("TlogReader for {:?} finished", self.path);
+        Ok(())
+    }
+
+    #[instrument(level = "debug", skip(self))]
+    fn info(&self) -> DriverInfo {
+        DriverInfo {
+            name: "TlogReader".to_string(),
+            endpoint: None,
+            filter: self.filter.clone(),
+        }
+    }
+
+    #[instrument(level = "debug", skip(self))]
+    fn stats(&self) -> DriverStats {
+        self.stats.snapshot()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use mavlink::MavlinkVersion;
+
+    use super::*;
+    use crate::protocol::fixtures::heartbeat_frame;
+
+    fn record(timestamp: u64, sequence: u8) -> Vec<u8> {
+        let mut record = timestamp.to_be_bytes().to_vec();
+        record.extend_from_slice(&heartbeat_frame(MavlinkVersion::V2, sequence));
+        record
+    }
+
+    async fn replay(name: &str, bytes: &[u8]) -> (Result<usize>, Vec<u8>, DriverStats) {
+        let path =
+            std::env::temp_dir().join(format!("mavlink-server-{}-{name}.tlog", std::process::id()));
+        std::fs::write(&path, bytes).unwrap();
+
+        let reader = TlogReader::new(
+            path.to_str().unwrap(),
+            Filter::default(),
+            1.,
+            false,
+            None,
+            None,
+        );
+        let (hub_sender, mut hub_receiver) = broadcast::channel(10);
+        let result = reader.replay(&hub_sender, &Router::default()).await;
+        std::fs::remove_file(&path).unwrap();
+
+        let mut sequences = vec![];
+        while let Ok(message) = hub_receiver.try_recv() {
+            sequences.push(message.sequence());
+        }
+
+        (result, sequences, reader.stats())
+    }
+
+    #[tokio::test]
+    async fn truncated_last_record_is_ignored() {
+        let mut bytes = [record(0, 0), record(1, 1)].concat();
+        let last_record = record(2, 2);
+
+        // Cut off in the timestamp, the STX, the header and the payload
+        for cut in [4, 8, 9, 14] {
+            let mut bytes = bytes.clone();
+            bytes.extend_from_slice(&last_record[..cut]);
+
+            let (result, sequences, stats) = replay(&format!("truncated-{cut}"), &bytes).await;
+            assert_eq!(result.unwrap(), 2);
+            assert_eq!(sequences, vec![0, 1]);
+            assert_eq!(stats.bytes_discarded, cut as u64);
+        }
+
+        bytes.extend_from_slice(&last_record);
+        let (result, sequences, _) = replay("complete", &bytes).await;
+        assert_eq!(result.unwrap(), 3);
+        assert_eq!(sequences, vec![0, 1, 2]);
+    }
+
+    #[tokio::test]
+    async fn corrupted_record_resynchronizes() {
+        let mut corrupted = record(1, 1);
+        corrupted[8] = 0x00; // Invalid STX
+        let bytes = [record(0, 0), vec![0xFD, 0x42], corrupted, record(2, 2)].concat();
+
+        let (result, sequences, stats) = replay("corrupted", &bytes).await;
+        assert_eq!(result.unwrap(), 2);
+        assert_eq!(sequences, vec![0, 2]);
+        assert!(stats.bytes_discarded > 0);
+    }
+}
@@ -0,0 +1,276 @@
+use std::{
+    fs::{File, OpenOptions},
+    io::{BufWriter, Write},
+    path::{Path, PathBuf},
+    sync::Arc,
+};
+
+use anyhow::Result;
+use tokio::{
+    sync::broadcast,
+    time::{Duration, Instant, MissedTickBehavior},
+};
+use tracing::*;
+
+use crate::{
+    drivers::{
+        filter::Filter,
+        stats::{DriverStats, DriverStatsCounters},
+        Driver, DriverInfo,
+    },
+    protocol::Protocol,
+    router::Router,
+};
+
+/// How often the buffered records are flushed to the file
+const FLUSH_PERIOD: Duration = Duration::from_secs(1);
+
+/// How many suffixes (e.g. "-1") are tried when the file name is already taken
+const MAX_NAME_SUFFIX: u32 = 100;
+
+/// Records every frame from the hub into `.tlog` files, where each record is the 8-byte big-endian
+/// UNIX timestamp in microseconds followed by the raw MAVLink frame
+pub struct TlogWriter {
+    pub directory: PathBuf,
+    pub filter: Filter,
+    /// Starts a new file when the current one would grow beyond this size, in bytes
+    pub max_file_size: Option<u64>,
+    /// Starts a new file when the current one is older than this
+    pub max_file_duration: Option<Duration>,
+    stats: Arc<DriverStatsCounters>,
+}
+
+/// The writer is synchronous, so whatever is still buffered is flushed when the file is dropped,
+/// including when the driver task is aborted (e.g. the driver is removed or on Ctrl-C)
+struct TlogFile {
+    path: PathBuf,
+    writer: BufWriter<File>,
+    size: u64,
+    created: Instant,
+}
+
+impl TlogFile {
+    fn flush(&mut self) {
+        if let Err(error) = self.writer.flush() {
+            error!("Failed to flush telemetry log {:?}: {error:?}", self.path);
+        }
+    }
+}
+
+impl Drop for TlogFile {
+    fn drop(&mut self) {
+        self.flush();
+    }
+}
+
+impl TlogWriter {
+    #[instrument(level = "debug")]
+    pub fn new(
+        directory: &str,
+        filter: Filter,
+        max_file_size: Option<u64>,
+        max_file_duration: Option<Duration>,
+    ) -> Self {
+        Self {
+            directory: PathBuf::from(directory),
+            filter,
+            max_file_size,
+            max_file_duration,
+            stats: Arc::new(DriverStatsCounters::default()),
+        }
+    }
+
+    /// Creates a new file named after the current time, never reusing an existing one (e.g. from
+    /// another writer on the same directory), which gets a suffix instead
+    #[instrument(level = "debug")]
+    async fn create_file(directory: &Path) -> Result<TlogFile> {
+        tokio::fs::create_dir_all(directory).await?;
+
+        let name = chrono::Local::now().format("%Y%m%d-%H%M%S%.3f").to_string();
+
+        let mut suffix = 0;
+        loop {
+            let path = match suffix {
+                0 => directory.join(format!("{name}.tlog")),
+                suffix => directory.join(format!("{name}-{suffix}.tlog")),
+            };
+
+            match OpenOptions::new().write(true).create_new(true).open(&path) {
+                Ok(file) => {
+                    info!("Recording telemetry log to {path:?}");
+
+                    return Ok(TlogFile {
+                        path,
+                        writer: BufWriter::new(file),
+                        size: 0,
+                        created: Instant::now(),
+                    });
+                }
+                Err(error)
+                    if error.kind() == std::io::ErrorKind::AlreadyExists
+                        && suffix < MAX_NAME_SUFFIX =>
+                {
+                    suffix += 1;
+                }
+                Err(error) => return Err(error.into()),
+            }
+        }
+    }
+
+    fn should_rotate(&self, file: &TlogFile, record_size: u64) -> bool {
+        let too_big = self
+            .max_file_size
+            .is_some_and(|max_file_size| file.size > 0 && file.size + record_size > max_file_size);
+        let too_old = self
+            .max_file_duration
+            .is_some_and(|max_file_duration| file.created.elapsed() > max_file_duration);
+
+        too_big || too_old
+    }
+
+    fn write_record(&self, file: &mut TlogFile, timestamp: u64, message: &Protocol) -> Result<()> {
+        let raw_bytes = message.raw_bytes();
+
+        file.writer.write_all(&timestamp.to_be_bytes())?;
+        file.writer.write_all(raw_bytes)?;
+        file.size += (std::mem::size_of::<u64>() + raw_bytes.len()) as u64;
+        self.stats.record_sent(raw_bytes.len());
+
+        trace!("Message written to tlog: {message:?}");
+        Ok(())
+    }
+
+    /// Records the hub messages into the file until it should be rotated, returning the record
+    /// that didn't fit, or `None` when the hub is closed
+    async fn record(
+        &self,
+        file: &mut TlogFile,
+        hub_receiver: &mut broadcast::Receiver<Protocol>,
+    ) -> Result<Option<(u64, Protocol)>> {
+        // Flushes even when no message arrives, so an idle hub doesn't leave records behind
+        let mut flush_interval = tokio::time::interval(FLUSH_PERIOD);
+        flush_interval.set_missed_tick_behavior(MissedTickBehavior::Delay);
+
+        loop {
+            let message = tokio::select! {
+                _ = flush_interval.tick() => {
+                    file.flush();
+                    continue;
+                }
+                message = hub_receiver.recv() => message,
+            };
+
+            let message = match message {
+                Ok(message) => message,
+                Err(broadcast::error::RecvError::Closed) => {
+                    error!("Hub channel closed!");
+                    return Ok(None);
+                }
+                Err(broadcast::error::RecvError::Lagged(count)) => {
+                    warn!("Channel lagged by {count} messages.");
+                    self.stats.record_lagged(count);
+                    continue;
+                }
+            };
+
+            if !self.filter.accepts_outgoing(&message) {
+                continue;
+            }
+
+            let timestamp = chrono::Utc::now().timestamp_micros() as u64;
+            let record_size = (std::mem::size_of::<u64>() + message.raw_bytes().len()) as u64;
+
+            if self.should_rotate(file, record_size) {
+                return Ok(Some((timestamp, message)));
+            }
+
+            self.write_record(file, timestamp, &message)?;
+        }
+    }
+}
+
+#[async_trait::async_trait]
+impl Driver for TlogWriter {
+    #[instrument(level = "debug", skip(self, hub_sender, _router))]
+    async fn run(
+        &self,
+        hub_sender: broadcast::Sender<Protocol>,
+        _router: Arc<Router>,
+    ) -> Result<()> {
+        let mut hub_receiver = hub_sender.subscribe();
+        let mut pending_record = None;
+
+        loop {
+            // The previous file, if any, was flushed when dropped
+            let mut file = match Self::create_file(&self.directory).await {
+                Ok(file) => file,
+                Err(error) => {
+                    error!(
+                        "Failed creating telemetry log in {:?}: {error:?}",
+                        self.directory
+                    );
+                    tokio::time::sleep(Duration::from_secs(1)).await;
+                    continue;
+                }
+            };
+
+            let result = async {
+                if let Some((timestamp, message)) = pending_record.take() {
+                    self.write_record(&mut file, timestamp, &message)?;
+                }
+                self.record(&mut file, &mut hub_receiver).await
+            }
+            .await;
+
+            match result {
+                Ok(Some(record)) => pending_record = Some(record),
+                Ok(None) => break,
+                Err(error) => {
+                    // Records are lost until a new file can be written, e.g. when out of space
+                    error!("Failed writing telemetry log {:?}: {error:?}", file.path);
+                    tokio::time::sleep(Duration::from_secs(1)).await;
+                }
+            }
+        }
+
+        debug!("TlogWriter for {:?} finished", self.directory);
+        Ok(())
+    }
+
+    #[instrument(level = "debug", skip(self))]
+    fn info(&self) -> DriverInfo {
+        DriverInfo {
+            name: "TlogWriter".to_string(),
+            endpoint: None,
+            filter: self.filter.clone(),
+        }
+    }
+
+    #[instrument(level = "debug", skip(self))]
+    fn stats(&self) -> DriverStats {
+        self.stats.snapshot()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn files_created_at_once_dont_overwrite_each_other() {
+        let directory =
+            std::env::temp_dir().join(format!("mavlink-server-test-{}-tlogw", std::process::id()));
+        let _ = std::fs::remove_dir_all(&directory);
+
+        let (first, second) = tokio::join!(
+            TlogWriter::create_file(&directory),
+            TlogWriter::create_file(&directory)
+        );
+        let (first, second) = (first.unwrap(), second.unwrap());
+        assert_ne!(first.path, second.path);
+
+        drop((first, second));
+        assert_eq!(std::fs::read_dir(&directory).unwrap().count(), 2);
+        std::fs::remove_dir_all(&directory).unwrap();
+    }
+}
@@ -0,0 +1,272 @@
+use crate::{protocol::Protocol, router::Router};
+use anyhow::Result;
+use std::{net::SocketAddr, sync::Arc};
+use tokio::net::UdpSocket;
+use tokio::sync::{broadcast, RwLock};
+use tokio::time::{Duration, Instant};
+use tracing::*;
+
+use crate::drivers::{
+    filter::Filter,
+    stats::{DriverStats, DriverStatsCounters},
+    udp::forward_datagram,
+    Driver, DriverInfo,
+};
+
+/// The peer is dropped, going back to broadcast, when not heard from for longer than this
+const PEER_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Broadcasts to the subnet until a peer responds, then switches to unicast toward that peer, so
+/// ground control stations on the LAN can discover the vehicle
+pub struct UdpBroadcast {
+    pub broadcast_addr: String,
+    pub filter: Filter,
+    stats: Arc<DriverStatsCounters>,
+    /// The first peer that responded, and when it was last heard from
+    peer: Arc<RwLock<Option<(SocketAddr, Instant)>>>,
+}
+
+impl UdpBroadcast {
+    #[instrument(level = "debug")]
+    pub fn new(broadcast_addr: &str, filter: Filter) -> Self {
+        Self {
+            broadcast_addr: broadcast_addr.to_string(),
+            filter,
+            stats: Arc::new(DriverStatsCounters::default()),
+            peer: Arc::new(RwLock::new(None)),
+        }
+    }
+
+    #[instrument(level = "debug", skip(socket, hub_sender, peer, router, filter, stats))]
+    async fn udp_receive_task(
+        socket: Arc<UdpSocket>,
+        hub_sender: Arc<broadcast::Sender<Protocol>>,
+        peer: Arc<RwLock<Option<(SocketAddr, Instant)>>>,
+        router: &Router,
+        filter: &Filter,
+        stats: &DriverStatsCounters,
+    ) -> Result<()> {
+        let mut buf = Vec::with_capacity(1024);
+
+        loop {
+            buf.clear();
+
+            match socket.recv_buf_from(&mut buf).await {
+                Ok((bytes_received, client_socket_addr)) if bytes_received > 0 => {
+                    {
+                        let mut peer = peer.write().await;
+                        match peer.as_mut() {
+                            Some((peer_addr, last_seen)) if *peer_addr == client_socket_addr => {
+                                *last_seen = Instant::now();
+                            }
+                            Some(_) => (),
+                            None => {
+                                info!("Peer {client_socket_addr:?} responded, switching from broadcast to unicast");
+                                *peer = Some((client_socket_addr, Instant::now()));
+                                stats.add_peer(&client_socket_addr.to_string());
+                            }
+                        }
+                    }
+
+                    let client_addr = client_socket_addr.to_string();
+
+                    forward_datagram(
+                        &buf[..bytes_received],
+                        &client_addr,
+                        &hub_sender,
+                        router,
+                        filter,
+                        stats,
+                    )
+                    .await;
+                }
+                Ok((_, client_addr)) => {
+                    warn!("UDP connection closed by {client_addr}.");
+                    break;
+                }
+                Err(error) => {
+                    error!("Failed to receive UDP message: {error:?}");
+                    break;
+                }
+            }
+        }
+
+        debug!("UdpBroadcast Receiver task finished");
+        Ok(())
+    }
+
+    #[instrument(
+        level = "debug",
+        skip(socket, hub_receiver, peer, router, filter, stats)
+    )]
+    async fn udp_send_task(
+        socket: Arc<UdpSocket>,
+        broadcast_addr: SocketAddr,
+        mut hub_receiver: broadcast::Receiver<Protocol>,
+        peer: Arc<RwLock<Option<(SocketAddr, Instant)>>>,
+        router: &Router,
+        filter: &Filter,
+        stats: &DriverStatsCounters,
+    ) -> Result<()> {
+        loop {
+            match hub_receiver.recv().await {
+                Ok(message) => {
+                    let remote_socket_addr = Self::destination(broadcast_addr, &peer, stats).await;
+                    let remote_addr = remote_socket_addr.to_string();
+
+                    if message.origin.eq(&remote_addr) {
+                        continue; // Don't do loopback
+                    }
+
+                    if !router.should_forward(&message, &remote_addr) {
+                        continue; // Targeted to another link
+                    }
+
+                    if !filter.accepts_outgoing(&message) {
+                        continue;
+                    }
+
+                    match socket
+                        .send_to(message.raw_bytes(), remote_socket_addr)
+                        .await
+                    {
+                        Ok(_) => {
+                            stats.record_sent(message.raw_bytes().len());
+                        }
+                        Err(ref error) if error.kind() == std::io::ErrorKind::ConnectionRefused => {
+                            continue;
+                        }
+                        Err(error) => {
+                            error!("Failed to send UDP message to {remote_addr}: {error:?}");
+                            break;
+                        }
+                    }
+                }
+                Err(broadcast::error::RecvError::Closed) => {
+                    error!("Hub channel closed!");
+                    break;
+                }
+                Err(broadcast::error::RecvError::Lagged(count)) => {
+                    warn!("Channel lagged by {count} messages.");
+                    stats.record_lagged(count);
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// The peer while it is alive, otherwise the broadcast address
+    async fn destination(
+        broadcast_addr: SocketAddr,
+        peer: &RwLock<Option<(SocketAddr, Instant)>>,
+        stats: &DriverStatsCounters,
+    ) -> SocketAddr {
+        let mut peer = peer.write().await;
+
+        match *peer {
+            Some((peer_addr, last_seen)) if last_seen.elapsed() < PEER_TIMEOUT => peer_addr,
+            Some((peer_addr, _)) => {
+                info!("Peer {peer_addr:?} went silent for {PEER_TIMEOUT:?}, switching back to broadcast");
+                stats.remove_peer(&peer_addr.to_string());
+                *peer = None;
+                broadcast_addr
+            }
+            None => broadcast_addr,
+        }
+    }
+}
+
+#[async_trait::async_trait]
+impl Driver for UdpBroadcast {
+    #[instrument(level = "debug", skip(self, hub_sender, router))]
+    async fn run(
+        &self,
+        hub_sender: broadcast::Sender<Protocol>,
+        router: Arc<Router>,
+    ) -> Result<()> {
+        let local_addr = "0.0.0.0:0";
+        let mut first_bind = true;
+
+        loop {
+            // IPv6 has no broadcast, so only IPv4 addresses are used
+            let broadcast_addr = match tokio::net::lookup_host(&self.broadcast_addr)
+                .await
+                .map(|mut addresses| addresses.find(SocketAddr::is_ipv4))
+            {
+                Ok(Some(broadcast_addr)) => broadcast_addr,
+                Ok(None) => {
+                    error!(
+                        "Failed resolving {:?}: no IPv4 address found",
+                        self.broadcast_addr
+                    );
+                    tokio::time::sleep(tokio::time::Duration::from_secs(1)).await;
+                    continue;
+                }
+                Err(error) => {
+                    error!("Failed resolving {:?}: {error:?}", self.broadcast_addr);
+                    tokio::time::sleep(tokio::time::Duration::from_secs(1)).await;
+                    continue;
+                }
+            };
+
+            let socket = match UdpSocket::bind(local_addr).await {
+                Ok(socket) => Arc::new(socket),
+                Err(error) => {
+                    error!("Failed binding UdpBroadcast to address {local_addr:?}: {error:?}");
+                    tokio::time::sleep(tokio::time::Duration::from_secs(1)).await;
+                    continue;
+                }
+            };
+
+            if let Err(error) = socket.set_broadcast(true) {
+                error!("Failed enabling broadcast on UdpBroadcast socket: {error:?}");
+                tokio::time::sleep(tokio::time::Duration::from_secs(1)).await;
+                continue;
+            }
+
+            debug!("UdpBroadcast successfully bound to {local_addr}, broadcasting to {broadcast_addr:?}");
+
+            if !first_bind {
+                self.stats.record_reconnection();
+
+                // The peer replied to the previous socket, so broadcast until it replies to this one
+                if let Some((peer_addr, _)) = self.peer.write().await.take() {
+                    self.stats.remove_peer(&peer_addr.to_string());
+                }
+            }
+            first_bind = false;
+
+            let hub_sender = Arc::new(hub_sender.clone());
+            let hub_receiver = hub_sender.subscribe();
+
+            tokio::select! {
+                result = UdpBroadcast::udp_receive_task(socket.clone(), hub_sender, self.peer.clone(), &router, &self.filter, &self.stats) => {
+                    if let Err(error) = result {
+                        error!("Error in receiving UDP messages: {error:?}");
+                    }
+                }
+                result = UdpBroadcast::udp_send_task(socket, broadcast_addr, hub_receiver, self.peer.clone(), &router, &self.filter, &self.stats) => {
+                    if let Err(error) = result {
+                        error!("Error in sending UDP messages: {error:?}");
+                    }
+                }
+            }
+
+            tokio::time::sleep(tokio::time::Duration::from_secs(1)).await;
+        }
+    }
+
+    #[instrument(level = "debug", skip(self))]
+    fn info(&self) -> DriverInfo {
+        DriverInfo {
+            name: "UdpBroadcast".to_string(),
+            endpoint: None,
+            filter: self.filter.clone(),
+        }
+    }
+
+    #[instrument(level = "debug", skip(self))]
+    fn stats(&self) -> DriverStats {
+        self.stats.snapshot()
+    }
+}
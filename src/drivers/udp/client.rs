@@ -1,29 +1,40 @@
-use crate::protocol::Protocol;
+use crate::{protocol::Protocol, router::Router};
 use anyhow::Result;
-use mavlink::ardupilotmega::MavMessage;
 use std::sync::Arc;
 use tokio::net::UdpSocket;
 use tokio::sync::broadcast;
 use tracing::*;
 
-use crate::drivers::{Driver, DriverInfo};
+use crate::drivers::{
+    filter::Filter,
+    stats::{DriverStats, DriverStatsCounters},
+    udp::{forward_datagram, unspecified_local_addr},
+    Driver, DriverInfo,
+};
 
 pub struct UdpClient {
     pub remote_addr: String,
+    pub filter: Filter,
+    stats: Arc<DriverStatsCounters>,
 }
 
 impl UdpClient {
     #[instrument(level = "debug")]
-    pub fn new(remote_addr: &str) -> Self {
+    pub fn new(remote_addr: &str, filter: Filter) -> Self {
         Self {
             remote_addr: remote_addr.to_string(),
+            filter,
+            stats: Arc::new(DriverStatsCounters::default()),
         }
     }
 
-    #[instrument(level = "debug", skip(socket))]
+    #[instrument(level = "debug", skip(socket, router, filter, stats))]
     async fn udp_receive_task(
         socket: Arc<UdpSocket>,
         hub_sender: Arc<broadcast::Sender<Protocol>>,
+        router: &Router,
+        filter: &Filter,
+        stats: &DriverStatsCounters,
     ) -> Result<()> {
         let mut buf = Vec::with_capacity(1024);
 
@@ -34,24 +45,15 @@ impl UdpClient {
                 Ok((bytes_received, client_addr)) if bytes_received > 0 => {
                     let client_addr = client_addr.to_string();
 
-                    let message = match mavlink::read_v2_raw_message_async::<MavMessage, _>(
-                        &mut (&buf[..bytes_received]),
+                    forward_datagram(
+                        &buf[..bytes_received],
+                        &client_addr,
+                        &hub_sender,
+                        router,
+                        filter,
+                        stats,
                     )
-                    .await
-                    {
-                        Ok(message) => message,
-                        Err(error) => {
-                            error!("Failed to parse MAVLink message: {error:?}");
-                            continue; // Skip this iteration on error
-                        }
-                    };
-
-                    let message = Protocol::new(&client_addr, message);
-
-                    trace!("Received UDP message: {message:?}");
-                    if let Err(error) = hub_sender.send(message) {
-                        error!("Failed to send message to hub: {error:?}");
-                    }
+                    .await;
                 }
                 Ok((_, client_addr)) => {
                     warn!("UDP connection closed by {client_addr}.");
@@ -68,21 +70,34 @@ impl UdpClient {
         Ok(())
     }
 
-    #[instrument(level = "debug", skip(socket))]
+    #[instrument(level = "debug", skip(socket, router, filter, stats))]
     async fn udp_send_task(
         socket: Arc<UdpSocket>,
         mut hub_receiver: broadcast::Receiver<Protocol>,
+        router: &Router,
+        filter: &Filter,
+        stats: &DriverStatsCounters,
     ) -> Result<()> {
         loop {
             match hub_receiver.recv().await {
                 Ok(message) => {
-                    if message.origin.eq(&socket.peer_addr()?.to_string()) {
+                    let remote_addr = socket.peer_addr()?.to_string();
+
+                    if message.origin.eq(&remote_addr) {
                         continue; // Don't do loopback
                     }
 
+                    if !router.should_forward(&message, &remote_addr) {
+                        continue; // Targeted to another link
+                    }
+
+                    if !filter.accepts_outgoing(&message) {
+                        continue;
+                    }
+
                     match socket.send(message.raw_bytes()).await {
                         Ok(_) => {
-                            // Message sent successfully
+                            stats.record_sent(message.raw_bytes().len());
                         }
                         Err(ref error) if error.kind() == std::io::ErrorKind::ConnectionRefused => {
                             // error!("UDP connection refused: {error:?}");
@@ -94,8 +109,13 @@ impl UdpClient {
                         }
                     }
                 }
-                Err(error) => {
-                    error!("Failed to receive message from hub: {error:?}");
+                Err(broadcast::error::RecvError::Closed) => {
+                    error!("Hub channel closed!");
+                    break;
+                }
+                Err(broadcast::error::RecvError::Lagged(count)) => {
+                    warn!("Channel lagged by {count} messages.");
+                    stats.record_lagged(count);
                 }
             }
         }
@@ -105,12 +125,35 @@ impl UdpClient {
 
 #[async_trait::async_trait]
 impl Driver for UdpClient {
-    #[instrument(level = "debug", skip(self, hub_sender))]
-    async fn run(&self, hub_sender: broadcast::Sender<Protocol>) -> Result<()> {
-        let local_addr = "0.0.0.0:0";
+    #[instrument(level = "debug", skip(self, hub_sender, router))]
+    async fn run(
+        &self,
+        hub_sender: broadcast::Sender<Protocol>,
+        router: Arc<Router>,
+    ) -> Result<()> {
         let remote_addr = self.remote_addr.clone();
+        let mut first_connection = true;
 
         loop {
+            // Resolved on every connection, since hostnames can change their address
+            let remote_socket_addr = match tokio::net::lookup_host(&remote_addr)
+                .await
+                .map(|mut addresses| addresses.next())
+            {
+                Ok(Some(remote_socket_addr)) => remote_socket_addr,
+                Ok(None) => {
+                    error!("Failed resolving {remote_addr:?}: no address found");
+                    tokio::time::sleep(tokio::time::Duration::from_secs(1)).await;
+                    continue;
+                }
+                Err(error) => {
+                    error!("Failed resolving {remote_addr:?}: {error:?}");
+                    tokio::time::sleep(tokio::time::Duration::from_secs(1)).await;
+                    continue;
+                }
+            };
+            let local_addr = unspecified_local_addr(&remote_socket_addr);
+
             let socket = match UdpSocket::bind(local_addr).await {
                 Ok(socket) => Arc::new(socket),
                 Err(error) => {
@@ -122,7 +165,7 @@ impl Driver for UdpClient {
 
             debug!("UdpClient successfully bound to {local_addr}. Connecting UdpClient to {remote_addr:?}...");
 
-            if let Err(error) = socket.connect(&remote_addr).await {
+            if let Err(error) = socket.connect(remote_socket_addr).await {
                 error!("Failed connecting UdpClient to {remote_addr:?}: {error:?}");
                 tokio::time::sleep(tokio::time::Duration::from_secs(1)).await;
                 continue;
@@ -130,21 +173,29 @@ impl Driver for UdpClient {
 
             debug!("UdpClient successfully connected to {remote_addr:?}");
 
+            if !first_connection {
+                self.stats.record_reconnection();
+            }
+            first_connection = false;
+            self.stats.add_peer(&remote_addr);
+
             let hub_sender = Arc::new(hub_sender.clone());
             let hub_receiver = hub_sender.subscribe();
 
             tokio::select! {
-                result = UdpClient::udp_receive_task(socket.clone(), hub_sender) => {
+                result = UdpClient::udp_receive_task(socket.clone(), hub_sender, &router, &self.filter, &self.stats) => {
                     if let Err(error) = result {
                         error!("Error in receiving UDP messages: {error:?}");
                     }
                 }
-                result = UdpClient::udp_send_task(socket, hub_receiver) => {
+                result = UdpClient::udp_send_task(socket, hub_receiver, &router, &self.filter, &self.stats) => {
                     if let Err(error) = result {
                         error!("Error in sending UDP messages: {error:?}");
                     }
                 }
             }
+
+            self.stats.remove_peer(&remote_addr);
         }
     }
 
@@ -152,6 +203,13 @@ impl Driver for UdpClient {
     fn info(&self) -> DriverInfo {
         DriverInfo {
             name: "UdpClient".to_string(),
+            endpoint: None,
+            filter: self.filter.clone(),
         }
     }
+
+    #[instrument(level = "debug", skip(self))]
+    fn stats(&self) -> DriverStats {
+        self.stats.snapshot()
+    }
 }
@@ -1,2 +1,62 @@
+use std::net::{Ipv4Addr, Ipv6Addr, SocketAddr};
+
+use tracing::*;
+
+use crate::{
+    drivers::{decoder::StreamDecoder, filter::Filter, stats::DriverStatsCounters},
+    protocol::Protocol,
+    router::Router,
+};
+
+pub mod broadcast;
 pub mod client;
+pub mod multicast;
 pub mod server;
+
+/// Unspecified local address, with an ephemeral port, of the same family as the remote address
+fn unspecified_local_addr(remote_addr: &SocketAddr) -> SocketAddr {
+    match remote_addr {
+        SocketAddr::V4(_) => SocketAddr::new(Ipv4Addr::UNSPECIFIED.into(), 0),
+        SocketAddr::V6(_) => SocketAddr::new(Ipv6Addr::UNSPECIFIED.into(), 0),
+    }
+}
+
+/// Decodes the frames of a datagram (UDP or Unix), which may carry several frames of any MAVLink
+/// version, and sends the accepted ones to the HUB Channel
+#[instrument(level = "trace", skip(datagram, hub_sender, router, filter, stats))]
+pub(crate) async fn forward_datagram(
+    datagram: &[u8],
+    origin: &str,
+    hub_sender: &tokio::sync::broadcast::Sender<Protocol>,
+    router: &Router,
+    filter: &Filter,
+    stats: &DriverStatsCounters,
+) {
+    let mut decoder = StreamDecoder::new();
+    decoder.push(datagram);
+
+    while let Some(message) = decoder.next_frame().await {
+        stats.record_received(message.raw_bytes().len());
+
+        if !filter.accepts_incoming(&message) {
+            continue;
+        }
+
+        let message = Protocol::new(origin, message);
+
+        trace!("Received datagram message: {message:?}");
+        if let Err(error) = router.send_to_hub(hub_sender, message) {
+            error!("Failed to send message to hub: {error:?}");
+        }
+    }
+
+    stats.record_parse_errors(decoder.take_parse_errors());
+    stats.record_discarded(decoder.take_bytes_discarded());
+
+    if decoder.bytes_discarded() > 0 {
+        warn!(
+            "Discarded {} invalid bytes from {origin}",
+            decoder.bytes_discarded()
+        );
+    }
+}
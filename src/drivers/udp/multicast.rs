@@ -0,0 +1,341 @@
+use crate::{protocol::Protocol, router::Router};
+use anyhow::{anyhow, Context, Result};
+use socket2::{Domain, Socket, Type};
+use std::{
+    net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr, SocketAddrV6},
+    sync::Arc,
+};
+use tokio::net::UdpSocket;
+use tokio::sync::broadcast;
+use tracing::*;
+
+use crate::drivers::{
+    filter::Filter,
+    stats::{DriverStats, DriverStatsCounters},
+    udp::forward_datagram,
+    Driver, DriverInfo,
+};
+
+/// Interface used to join the group and to send to it
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum MulticastInterface {
+    /// Address of the interface, unspecified for the default one
+    V4(Ipv4Addr),
+    /// Index of the interface, 0 for the default one
+    V6(u32),
+}
+
+/// Joins a multicast group, receiving from all of its members and sending the hub traffic to it
+pub struct UdpMulticast {
+    pub address: String,
+    pub filter: Filter,
+    /// Time to live (IPv4) or hop limit (IPv6) of the sent datagrams
+    pub ttl: u32,
+    /// Delivers the sent datagrams to the members on this same host
+    pub multicast_loop: bool,
+    stats: Arc<DriverStatsCounters>,
+}
+
+/// Parses a "group:port[:interface]" address, where IPv6 groups are in brackets, e.g.:
+/// "239.255.0.1:14550:192.168.2.2" or "[ff02::1]:14550:2".
+/// The interface is an address for IPv4 groups and an interface index for IPv6 groups.
+pub fn parse_address(address: &str) -> Result<(SocketAddr, MulticastInterface)> {
+    let (group, rest) = match address.strip_prefix('[') {
+        Some(address) => {
+            let (group, rest) = address
+                .split_once(']')
+                .context("Missing closing bracket of IPv6 group")?;
+            let rest = rest
+                .strip_prefix(':')
+                .context("Missing port of multicast group")?;
+            (group, rest)
+        }
+        None => address
+            .split_once(':')
+            .context("Missing port of multicast group")?,
+    };
+
+    let group: IpAddr = group
+        .parse()
+        .context(format!("Invalid multicast group: {group:?}"))?;
+    if !group.is_multicast() {
+        return Err(anyhow!("{group:?} is not a multicast address"));
+    }
+
+    let (port, interface) = match rest.split_once(':') {
+        Some((port, interface)) => (port, Some(interface)),
+        None => (rest, None),
+    };
+    let port: u16 = port.parse().context(format!("Invalid port: {port:?}"))?;
+
+    let interface = match group {
+        IpAddr::V4(_) => MulticastInterface::V4(
+            interface
+                .map(str::parse)
+                .transpose()
+                .context("Interface of IPv4 groups should be an IPv4 address")?
+                .unwrap_or(Ipv4Addr::UNSPECIFIED),
+        ),
+        IpAddr::V6(_) => MulticastInterface::V6(
+            interface
+                .map(str::parse)
+                .transpose()
+                .context("Interface of IPv6 groups should be an interface index")?
+                .unwrap_or(0),
+        ),
+    };
+
+    Ok((SocketAddr::new(group, port), interface))
+}
+
+impl UdpMulticast {
+    #[instrument(level = "debug")]
+    pub fn new(address: &str, filter: Filter, ttl: u32, multicast_loop: bool) -> Self {
+        Self {
+            address: address.to_string(),
+            filter,
+            ttl,
+            multicast_loop,
+            stats: Arc::new(DriverStatsCounters::default()),
+        }
+    }
+
+    /// Socket bound to the group port, shared with other members on this host, that joins the group
+    #[instrument(level = "debug")]
+    fn receive_socket(group: SocketAddr, interface: MulticastInterface) -> Result<UdpSocket> {
+        let socket = Socket::new(
+            Domain::for_address(group),
+            Type::DGRAM,
+            Some(socket2::Protocol::UDP),
+        )?;
+        socket.set_reuse_address(true)?;
+
+        match (group.ip(), interface) {
+            (IpAddr::V4(group_ip), MulticastInterface::V4(interface)) => {
+                socket.bind(&SocketAddr::new(Ipv4Addr::UNSPECIFIED.into(), group.port()).into())?;
+                socket.join_multicast_v4(&group_ip, &interface)?;
+            }
+            (IpAddr::V6(group_ip), MulticastInterface::V6(interface)) => {
+                socket.set_only_v6(true)?;
+                socket.bind(&SocketAddr::new(Ipv6Addr::UNSPECIFIED.into(), group.port()).into())?;
+                socket.join_multicast_v6(&group_ip, interface)?;
+            }
+            _ => return Err(anyhow!("Interface should match the group address family")),
+        }
+
+        socket.set_nonblocking(true)?;
+        Ok(UdpSocket::from_std(socket.into())?)
+    }
+
+    /// Socket bound to an ephemeral port of the interface that sends to the group
+    #[instrument(level = "debug")]
+    fn send_socket(
+        group: SocketAddr,
+        interface: MulticastInterface,
+        ttl: u32,
+        multicast_loop: bool,
+    ) -> Result<UdpSocket> {
+        let socket = Socket::new(
+            Domain::for_address(group),
+            Type::DGRAM,
+            Some(socket2::Protocol::UDP),
+        )?;
+
+        match interface {
+            MulticastInterface::V4(interface) => {
+                socket.bind(&SocketAddr::new(interface.into(), 0).into())?;
+                socket.set_multicast_if_v4(&interface)?;
+                socket.set_multicast_ttl_v4(ttl)?;
+                socket.set_multicast_loop_v4(multicast_loop)?;
+            }
+            MulticastInterface::V6(interface) => {
+                socket.bind(&SocketAddr::new(Ipv6Addr::UNSPECIFIED.into(), 0).into())?;
+                socket.set_multicast_if_v6(interface)?;
+                socket.set_multicast_hops_v6(ttl)?;
+                socket.set_multicast_loop_v6(multicast_loop)?;
+            }
+        }
+
+        // Connecting makes the host pick the source address now, so our own datagrams can be told
+        // apart from the ones of members on other hosts sending from the same port
+        let destination = match (group, interface) {
+            (SocketAddr::V6(group), MulticastInterface::V6(interface)) => {
+                SocketAddrV6::new(*group.ip(), group.port(), 0, interface).into()
+            }
+            _ => group,
+        };
+        socket.connect(&destination.into())?;
+
+        socket.set_nonblocking(true)?;
+        Ok(UdpSocket::from_std(socket.into())?)
+    }
+
+    #[instrument(level = "debug", skip(socket, hub_sender, router, filter, stats))]
+    async fn udp_receive_task(
+        socket: UdpSocket,
+        group_addr: &str,
+        own_addr: SocketAddr,
+        hub_sender: Arc<broadcast::Sender<Protocol>>,
+        router: &Router,
+        filter: &Filter,
+        stats: &DriverStatsCounters,
+    ) -> Result<()> {
+        let mut buf = Vec::with_capacity(1024);
+
+        loop {
+            buf.clear();
+
+            match socket.recv_buf_from(&mut buf).await {
+                Ok((_, member_addr))
+                    if member_addr.ip() == own_addr.ip()
+                        && member_addr.port() == own_addr.port() =>
+                {
+                    continue; // Our own datagram, looped back by the host
+                }
+                Ok((bytes_received, member_addr)) if bytes_received > 0 => {
+                    trace!("Received {bytes_received} bytes from member {member_addr:?}");
+
+                    // The whole group is a single link, so its traffic is never sent back to it
+                    forward_datagram(
+                        &buf[..bytes_received],
+                        group_addr,
+                        &hub_sender,
+                        router,
+                        filter,
+                        stats,
+                    )
+                    .await;
+                }
+                Ok((_, member_addr)) => {
+                    warn!("Empty datagram from {member_addr}, ignoring it.");
+                }
+                Err(error) => {
+                    error!("Failed to receive UDP message: {error:?}");
+                    break;
+                }
+            }
+        }
+
+        debug!("UdpMulticast Receiver task finished");
+        Ok(())
+    }
+
+    #[instrument(level = "debug", skip(socket, hub_receiver, router, filter, stats))]
+    async fn udp_send_task(
+        socket: UdpSocket,
+        group: SocketAddr,
+        mut hub_receiver: broadcast::Receiver<Protocol>,
+        router: &Router,
+        filter: &Filter,
+        stats: &DriverStatsCounters,
+    ) -> Result<()> {
+        let group_addr = group.to_string();
+
+        loop {
+            match hub_receiver.recv().await {
+                Ok(message) => {
+                    if message.origin.eq(&group_addr) {
+                        continue; // Don't do loopback
+                    }
+
+                    if !router.should_forward(&message, &group_addr) {
+                        continue; // Targeted to another link
+                    }
+
+                    if !filter.accepts_outgoing(&message) {
+                        continue;
+                    }
+
+                    match socket.send_to(message.raw_bytes(), group).await {
+                        Ok(_) => {
+                            stats.record_sent(message.raw_bytes().len());
+                        }
+                        Err(error) => {
+                            error!("Failed to send UDP message to {group_addr}: {error:?}");
+                            break;
+                        }
+                    }
+                }
+                Err(broadcast::error::RecvError::Closed) => {
+                    error!("Hub channel closed!");
+                    break;
+                }
+                Err(broadcast::error::RecvError::Lagged(count)) => {
+                    warn!("Channel lagged by {count} messages.");
+                    stats.record_lagged(count);
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+#[async_trait::async_trait]
+impl Driver for UdpMulticast {
+    #[instrument(level = "debug", skip(self, hub_sender, router))]
+    async fn run(
+        &self,
+        hub_sender: broadcast::Sender<Protocol>,
+        router: Arc<Router>,
+    ) -> Result<()> {
+        let (group, interface) = parse_address(&self.address)?;
+        let group_addr = group.to_string();
+        let mut first_join = true;
+
+        loop {
+            let sockets = Self::receive_socket(group, interface).and_then(|receive_socket| {
+                let send_socket =
+                    Self::send_socket(group, interface, self.ttl, self.multicast_loop)?;
+                Ok((receive_socket, send_socket))
+            });
+            let (receive_socket, send_socket) = match sockets {
+                Ok(sockets) => sockets,
+                Err(error) => {
+                    error!("Failed joining multicast group {group_addr:?}: {error:?}");
+                    tokio::time::sleep(tokio::time::Duration::from_secs(1)).await;
+                    continue;
+                }
+            };
+            let own_addr = send_socket.local_addr()?;
+
+            debug!("UdpMulticast successfully joined {group_addr:?}, sending from {own_addr}");
+
+            if !first_join {
+                self.stats.record_reconnection();
+            }
+            first_join = false;
+
+            let hub_sender = Arc::new(hub_sender.clone());
+            let hub_receiver = hub_sender.subscribe();
+
+            tokio::select! {
+                result = UdpMulticast::udp_receive_task(receive_socket, &group_addr, own_addr, hub_sender, &router, &self.filter, &self.stats) => {
+                    if let Err(error) = result {
+                        error!("Error in receiving UDP messages: {error:?}");
+                    }
+                }
+                result = UdpMulticast::udp_send_task(send_socket, group, hub_receiver, &router, &self.filter, &self.stats) => {
+                    if let Err(error) = result {
+                        error!("Error in sending UDP messages: {error:?}");
+                    }
+                }
+            }
+
+            tokio::time::sleep(tokio::time::Duration::from_secs(1)).await;
+        }
+    }
+
+    #[instrument(level = "debug", skip(self))]
+    fn info(&self) -> DriverInfo {
+        DriverInfo {
+            name: "UdpMulticast".to_string(),
+            endpoint: None,
+            filter: self.filter.clone(),
+        }
+    }
+
+    #[instrument(level = "debug", skip(self))]
+    fn stats(&self) -> DriverStats {
+        self.stats.snapshot()
+    }
+}
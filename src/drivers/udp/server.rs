@@ -1,32 +1,50 @@
 use anyhow::Result;
-use mavlink::ardupilotmega::MavMessage;
-use std::{collections::HashMap, sync::Arc};
+use std::{net::SocketAddr, sync::Arc};
 use tokio::net::UdpSocket;
-use tokio::sync::{broadcast, RwLock};
+use tokio::sync::broadcast;
+use tokio::time::Duration;
 use tracing::*;
 
-use crate::drivers::{Driver, DriverInfo};
-use crate::protocol::Protocol;
-
+use crate::drivers::{
+    clients::ClientTable,
+    filter::Filter,
+    stats::{DriverStats, DriverStatsCounters},
+    udp::forward_datagram,
+    Driver, DriverInfo,
+};
+use crate::{protocol::Protocol, router::Router};
+
+/// Receives from any client, sending the hub traffic to each client heard from in the last
+/// `client_timeout`
 pub struct UdpServer {
     pub local_addr: String,
-    clients: Arc<RwLock<HashMap<(u8, u8), String>>>,
+    pub filter: Filter,
+    stats: Arc<DriverStatsCounters>,
+    clients: ClientTable<SocketAddr>,
 }
 
 impl UdpServer {
     #[instrument(level = "debug")]
-    pub fn new(local_addr: &str) -> Self {
+    pub fn new(local_addr: &str, filter: Filter, client_timeout: Duration) -> Self {
         Self {
             local_addr: local_addr.to_string(),
-            clients: Arc::new(RwLock::new(HashMap::new())),
+            filter,
+            stats: Arc::new(DriverStatsCounters::default()),
+            clients: ClientTable::new(client_timeout),
         }
     }
 
-    #[instrument(level = "debug", skip(socket, hub_sender, clients))]
+    #[instrument(
+        level = "debug",
+        skip(socket, hub_sender, clients, router, filter, stats)
+    )]
     async fn udp_receive_task(
         socket: Arc<UdpSocket>,
         hub_sender: Arc<broadcast::Sender<Protocol>>,
-        clients: Arc<RwLock<HashMap<(u8, u8), String>>>,
+        clients: &ClientTable<SocketAddr>,
+        router: &Router,
+        filter: &Filter,
+        stats: &DriverStatsCounters,
     ) -> Result<()> {
         let mut buf = Vec::with_capacity(1024);
 
@@ -34,40 +52,19 @@ impl UdpServer {
             buf.clear();
 
             match socket.recv_buf_from(&mut buf).await {
-                Ok((bytes_received, client_addr)) if bytes_received > 0 => {
-                    let client_addr = client_addr.to_string();
-
-                    let message = match mavlink::read_v2_raw_message_async::<MavMessage, _>(
-                        &mut (&buf[..bytes_received]),
+                Ok((bytes_received, client_socket_addr)) if bytes_received > 0 => {
+                    let client_addr = client_socket_addr.to_string();
+                    clients.touch(client_socket_addr, &client_addr, stats).await;
+
+                    forward_datagram(
+                        &buf[..bytes_received],
+                        &client_addr,
+                        &hub_sender,
+                        router,
+                        filter,
+                        stats,
                     )
-                    .await
-                    {
-                        Ok(message) => message,
-                        Err(error) => {
-                            error!("Failed to parse MAVLink message: {error:?}");
-                            continue; // Skip this iteration on error
-                        }
-                    };
-
-                    let message = Protocol::new(&client_addr, message);
-
-                    // Update clients
-                    let header_buf = message.header();
-                    let sysid = header_buf[4];
-                    let compid = header_buf[5];
-                    if clients
-                        .write()
-                        .await
-                        .insert((sysid, compid), client_addr.clone())
-                        .is_none()
-                    {
-                        debug!("Client added: ({sysid},{compid}) -> {client_addr:?}");
-                    }
-
-                    trace!("Received UDP message: {message:?}");
-                    if let Err(error) = hub_sender.send(message) {
-                        error!("Failed to send message to hub: {error:?}");
-                    }
+                    .await;
                 }
                 Ok((_, client_addr)) => {
                     warn!("UDP connection closed by {client_addr}.");
@@ -84,23 +81,42 @@ impl UdpServer {
         Ok(())
     }
 
-    #[instrument(level = "debug", skip(socket, hub_receiver, clients))]
+    #[instrument(
+        level = "debug",
+        skip(socket, hub_receiver, clients, router, filter, stats)
+    )]
     async fn udp_send_task(
         socket: Arc<UdpSocket>,
         mut hub_receiver: broadcast::Receiver<Protocol>,
-        clients: Arc<RwLock<HashMap<(u8, u8), String>>>,
+        clients: &ClientTable<SocketAddr>,
+        router: &Router,
+        filter: &Filter,
+        stats: &DriverStatsCounters,
     ) -> Result<()> {
         loop {
             match hub_receiver.recv().await {
                 Ok(message) => {
-                    for ((_, _), client_addr) in clients.read().await.iter() {
+                    if !filter.accepts_outgoing(&message) {
+                        continue;
+                    }
+
+                    for (client_socket_addr, client) in clients.read().await.iter() {
+                        let client_addr = &client.name;
+
                         if message.origin.eq(client_addr) {
                             continue; // Don't do loopback
                         }
 
-                        match socket.send(message.raw_bytes()).await {
+                        if !router.should_forward(&message, client_addr) {
+                            continue; // Targeted to another link
+                        }
+
+                        match socket
+                            .send_to(message.raw_bytes(), *client_socket_addr)
+                            .await
+                        {
                             Ok(_) => {
-                                // Message sent successfully
+                                stats.record_sent(message.raw_bytes().len());
                             }
                             Err(ref error)
                                 if error.kind() == std::io::ErrorKind::ConnectionRefused =>
@@ -109,14 +125,19 @@ impl UdpServer {
                                 continue;
                             }
                             Err(error) => {
-                                error!("Failed to send UDP message: {error:?}");
-                                break;
+                                error!("Failed to send UDP message to {client_addr}: {error:?}");
+                                continue;
                             }
                         }
                     }
                 }
-                Err(error) => {
-                    error!("Failed to receive message from hub: {error:?}");
+                Err(broadcast::error::RecvError::Closed) => {
+                    error!("Hub channel closed!");
+                    break;
+                }
+                Err(broadcast::error::RecvError::Lagged(count)) => {
+                    warn!("Channel lagged by {count} messages.");
+                    stats.record_lagged(count);
                 }
             }
         }
@@ -126,10 +147,14 @@ impl UdpServer {
 
 #[async_trait::async_trait]
 impl Driver for UdpServer {
-    #[instrument(level = "debug", skip(self, hub_sender))]
-    async fn run(&self, hub_sender: broadcast::Sender<Protocol>) -> Result<()> {
+    #[instrument(level = "debug", skip(self, hub_sender, router))]
+    async fn run(
+        &self,
+        hub_sender: broadcast::Sender<Protocol>,
+        router: Arc<Router>,
+    ) -> Result<()> {
         let local_addr = &self.local_addr;
-        let clients = self.clients.clone();
+        let mut first_bind = true;
 
         loop {
             let socket = match UdpSocket::bind(local_addr).await {
@@ -141,20 +166,26 @@ impl Driver for UdpServer {
                 }
             };
 
+            if !first_bind {
+                self.stats.record_reconnection();
+            }
+            first_bind = false;
+
             let hub_sender = Arc::new(hub_sender.clone());
             let hub_receiver = hub_sender.subscribe();
 
             tokio::select! {
-                result = UdpServer::udp_receive_task(socket.clone(), hub_sender, clients.clone()) => {
+                result = UdpServer::udp_receive_task(socket.clone(), hub_sender, &self.clients, &router, &self.filter, &self.stats) => {
                     if let Err(error) = result {
                         error!("Error in receiving UDP messages: {error:?}");
                     }
                 }
-                result = UdpServer::udp_send_task(socket, hub_receiver, clients.clone()) => {
+                result = UdpServer::udp_send_task(socket, hub_receiver, &self.clients, &router, &self.filter, &self.stats) => {
                     if let Err(error) = result {
                         error!("Error in sending UDP messages: {error:?}");
                     }
                 }
+                _ = self.clients.expiry_task(&self.stats) => {}
             }
         }
     }
@@ -163,6 +194,13 @@ impl Driver for UdpServer {
     fn info(&self) -> DriverInfo {
         DriverInfo {
             name: "UdpServer".to_string(),
+            endpoint: None,
+            filter: self.filter.clone(),
         }
     }
+
+    #[instrument(level = "debug", skip(self))]
+    fn stats(&self) -> DriverStats {
+        self.stats.snapshot()
+    }
 }
@@ -0,0 +1,103 @@
+use std::sync::Arc;
+
+use crate::drivers::tcp::{tcp_receive_task, tcp_send_task};
+use crate::{protocol::Protocol, router::Router};
+use anyhow::Result;
+use tokio::net::UnixStream;
+use tokio::sync::broadcast;
+use tracing::*;
+
+use crate::drivers::{
+    filter::Filter,
+    stats::{DriverStats, DriverStatsCounters},
+    Driver, DriverInfo,
+};
+
+/// Connects to a local server on a Unix stream socket, framed the same way as TCP
+pub struct UnixClient {
+    pub path: String,
+    pub filter: Filter,
+    stats: Arc<DriverStatsCounters>,
+}
+
+impl UnixClient {
+    #[instrument(level = "debug")]
+    pub fn new(path: &str, filter: Filter) -> Self {
+        Self {
+            path: path.to_string(),
+            filter,
+            stats: Arc::new(DriverStatsCounters::default()),
+        }
+    }
+}
+
+#[async_trait::async_trait]
+impl Driver for UnixClient {
+    #[instrument(level = "debug", skip(self, hub_sender, router))]
+    async fn run(
+        &self,
+        hub_sender: broadcast::Sender<Protocol>,
+        router: Arc<Router>,
+    ) -> Result<()> {
+        let server_path = &self.path;
+        let hub_sender = Arc::new(hub_sender);
+
+        let mut first_connection = true;
+
+        loop {
+            debug!("Trying to connect to {server_path:?}...");
+            let socket = match UnixStream::connect(server_path).await {
+                Ok(socket) => socket,
+                Err(error) => {
+                    error!("Failed connecting to {server_path:?}: {error:?}");
+                    tokio::time::sleep(tokio::time::Duration::from_secs(1)).await;
+                    continue;
+                }
+            };
+            debug!("UnixClient successfully connected to {server_path:?}");
+
+            if !first_connection {
+                self.stats.record_reconnection();
+            }
+            first_connection = false;
+            self.stats.add_peer(server_path);
+
+            // Each task owns its half, so sending never waits on a pending read
+            let (reader, writer) = tokio::io::split(socket);
+            let hub_receiver = hub_sender.subscribe();
+            let hub_sender_cloned = Arc::clone(&hub_sender);
+
+            tokio::select! {
+                result = tcp_receive_task(reader, server_path, hub_sender_cloned, &router, &self.filter, &self.stats) => {
+                    if let Err(e) = result {
+                        error!("Error in Unix receive task: {e:?}");
+                    }
+                }
+                result = tcp_send_task(writer, server_path, hub_receiver, &router, &self.filter, &self.stats) => {
+                    if let Err(e) = result {
+                        error!("Error in Unix send task: {e:?}");
+                    }
+                }
+            }
+
+            self.stats.remove_peer(server_path);
+
+            debug!("Restarting Unix Client connection loop...");
+            tokio::time::sleep(tokio::time::Duration::from_secs(1)).await;
+        }
+    }
+
+    #[instrument(level = "debug", skip(self))]
+    fn info(&self) -> DriverInfo {
+        DriverInfo {
+            name: "UnixClient".to_string(),
+            endpoint: None,
+            filter: self.filter.clone(),
+        }
+    }
+
+    #[instrument(level = "debug", skip(self))]
+    fn stats(&self) -> DriverStats {
+        self.stats.snapshot()
+    }
+}
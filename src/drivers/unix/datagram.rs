@@ -0,0 +1,402 @@
+use std::{
+    path::{Path, PathBuf},
+    sync::Arc,
+};
+
+use crate::{protocol::Protocol, router::Router};
+use anyhow::Result;
+use tokio::net::UnixDatagram;
+use tokio::sync::broadcast;
+use tokio::time::Duration;
+use tracing::*;
+
+use crate::drivers::{
+    clients::ClientTable,
+    filter::Filter,
+    stats::{DriverStats, DriverStatsCounters},
+    udp::forward_datagram,
+    unix::{bind_socket, PrivateDir},
+    Driver, DriverInfo,
+};
+
+/// Receives from local clients on a Unix datagram socket, sending the hub traffic to each client
+/// bound to a path, as the unnamed ones can't be replied to
+pub struct UnixDatagramServer {
+    pub path: PathBuf,
+    pub filter: Filter,
+    /// Permissions of the socket file, e.g. 0o660
+    pub mode: Option<u32>,
+    stats: Arc<DriverStatsCounters>,
+    clients: ClientTable<PathBuf>,
+}
+
+impl UnixDatagramServer {
+    #[instrument(level = "debug")]
+    pub fn new(path: &str, filter: Filter, mode: Option<u32>, client_timeout: Duration) -> Self {
+        Self {
+            path: PathBuf::from(path),
+            filter,
+            mode,
+            stats: Arc::new(DriverStatsCounters::default()),
+            clients: ClientTable::new(client_timeout),
+        }
+    }
+
+    #[instrument(
+        level = "debug",
+        skip(socket, hub_sender, clients, router, filter, stats)
+    )]
+    async fn unix_receive_task(
+        socket: Arc<UnixDatagram>,
+        server_path: &Path,
+        hub_sender: Arc<broadcast::Sender<Protocol>>,
+        clients: &ClientTable<PathBuf>,
+        router: &Router,
+        filter: &Filter,
+        stats: &DriverStatsCounters,
+    ) -> Result<()> {
+        let mut buf = Vec::with_capacity(1024);
+
+        loop {
+            buf.clear();
+
+            let (bytes_received, client_socket_addr) = match socket.recv_buf_from(&mut buf).await {
+                Ok(received) => received,
+                Err(error) => {
+                    error!("Failed to receive Unix datagram: {error:?}");
+                    break;
+                }
+            };
+
+            let client_addr = match client_socket_addr.as_pathname() {
+                Some(client_path) => {
+                    let client_addr = client_path.display().to_string();
+                    clients
+                        .touch(client_path.to_path_buf(), &client_addr, stats)
+                        .await;
+
+                    client_addr
+                }
+                None => {
+                    trace!("Datagram from an unnamed socket, which can't be replied to");
+                    server_path.display().to_string()
+                }
+            };
+
+            forward_datagram(
+                &buf[..bytes_received],
+                &client_addr,
+                &hub_sender,
+                router,
+                filter,
+                stats,
+            )
+            .await;
+        }
+
+        debug!("UnixDatagramServer Receiver task finished");
+        Ok(())
+    }
+
+    #[instrument(
+        level = "debug",
+        skip(socket, hub_receiver, clients, router, filter, stats)
+    )]
+    async fn unix_send_task(
+        socket: Arc<UnixDatagram>,
+        mut hub_receiver: broadcast::Receiver<Protocol>,
+        clients: &ClientTable<PathBuf>,
+        router: &Router,
+        filter: &Filter,
+        stats: &DriverStatsCounters,
+    ) -> Result<()> {
+        loop {
+            match hub_receiver.recv().await {
+                Ok(message) => {
+                    if !filter.accepts_outgoing(&message) {
+                        continue;
+                    }
+
+                    for (client_path, client) in clients.read().await.iter() {
+                        if message.origin.eq(&client.name) {
+                            continue; // Don't do loopback
+                        }
+
+                        if !router.should_forward(&message, &client.name) {
+                            continue; // Targeted to another link
+                        }
+
+                        match socket.send_to(message.raw_bytes(), client_path).await {
+                            Ok(_) => {
+                                stats.record_sent(message.raw_bytes().len());
+                            }
+                            Err(error) => {
+                                // The client is gone, it will expire if it doesn't come back
+                                trace!(
+                                    "Failed to send Unix datagram to {}: {error:?}",
+                                    client.name
+                                );
+                                continue;
+                            }
+                        }
+                    }
+                }
+                Err(broadcast::error::RecvError::Closed) => {
+                    error!("Hub channel closed!");
+                    break;
+                }
+                Err(broadcast::error::RecvError::Lagged(count)) => {
+                    warn!("Channel lagged by {count} messages.");
+                    stats.record_lagged(count);
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+#[async_trait::async_trait]
+impl Driver for UnixDatagramServer {
+    #[instrument(level = "debug", skip(self, hub_sender, router))]
+    async fn run(
+        &self,
+        hub_sender: broadcast::Sender<Protocol>,
+        router: Arc<Router>,
+    ) -> Result<()> {
+        let path = &self.path;
+        let clients = &self.clients;
+        let mut first_bind = true;
+
+        loop {
+            // The socket file is removed with the guard, before binding again or when stopped
+            let (socket, _socket_file) = match bind_socket(path, self.mode, UnixDatagram::bind) {
+                Ok((socket, socket_file)) => (Arc::new(socket), socket_file),
+                Err(error) => {
+                    error!("Failed binding UnixDatagramServer to {path:?}: {error:?}");
+                    tokio::time::sleep(tokio::time::Duration::from_secs(1)).await;
+                    continue;
+                }
+            };
+
+            if !first_bind {
+                self.stats.record_reconnection();
+            }
+            first_bind = false;
+
+            let hub_sender = Arc::new(hub_sender.clone());
+            let hub_receiver = hub_sender.subscribe();
+
+            tokio::select! {
+                result = UnixDatagramServer::unix_receive_task(socket.clone(), path, hub_sender, clients, &router, &self.filter, &self.stats) => {
+                    if let Err(error) = result {
+                        error!("Error in receiving Unix datagrams: {error:?}");
+                    }
+                }
+                result = UnixDatagramServer::unix_send_task(socket, hub_receiver, clients, &router, &self.filter, &self.stats) => {
+                    if let Err(error) = result {
+                        error!("Error in sending Unix datagrams: {error:?}");
+                    }
+                }
+                _ = clients.expiry_task(&self.stats) => {}
+            }
+
+            tokio::time::sleep(tokio::time::Duration::from_secs(1)).await;
+        }
+    }
+
+    #[instrument(level = "debug", skip(self))]
+    fn info(&self) -> DriverInfo {
+        DriverInfo {
+            name: "UnixDatagramServer".to_string(),
+            endpoint: None,
+            filter: self.filter.clone(),
+        }
+    }
+
+    #[instrument(level = "debug", skip(self))]
+    fn stats(&self) -> DriverStats {
+        self.stats.snapshot()
+    }
+}
+
+/// Sends to a local server on a Unix datagram socket, from a socket bound in a private directory
+/// (in `$XDG_RUNTIME_DIR` or the temporary directory) so the server can reply. Only servers running
+/// as the same user can reach into that directory, others won't be able to reply.
+pub struct UnixDatagramClient {
+    pub server_path: PathBuf,
+    pub filter: Filter,
+    stats: Arc<DriverStatsCounters>,
+}
+
+impl UnixDatagramClient {
+    #[instrument(level = "debug")]
+    pub fn new(server_path: &str, filter: Filter) -> Self {
+        Self {
+            server_path: PathBuf::from(server_path),
+            filter,
+            stats: Arc::new(DriverStatsCounters::default()),
+        }
+    }
+
+    /// Binds the client socket in a new private directory, removed with the returned guard, and
+    /// connects it to the server
+    #[instrument(level = "debug")]
+    fn connect(server_path: &Path) -> Result<(UnixDatagram, PrivateDir)> {
+        let private_dir = PrivateDir::create()?;
+        let socket = UnixDatagram::bind(private_dir.path.join("client.sock"))?;
+        socket.connect(server_path)?;
+
+        Ok((socket, private_dir))
+    }
+
+    #[instrument(level = "debug", skip(socket, hub_sender, router, filter, stats))]
+    async fn unix_receive_task(
+        socket: Arc<UnixDatagram>,
+        server_addr: &str,
+        hub_sender: Arc<broadcast::Sender<Protocol>>,
+        router: &Router,
+        filter: &Filter,
+        stats: &DriverStatsCounters,
+    ) -> Result<()> {
+        let mut buf = Vec::with_capacity(1024);
+
+        loop {
+            buf.clear();
+
+            match socket.recv_buf(&mut buf).await {
+                Ok(bytes_received) => {
+                    forward_datagram(
+                        &buf[..bytes_received],
+                        server_addr,
+                        &hub_sender,
+                        router,
+                        filter,
+                        stats,
+                    )
+                    .await;
+                }
+                Err(error) => {
+                    error!("Failed to receive Unix datagram: {error:?}");
+                    break;
+                }
+            }
+        }
+
+        debug!("UnixDatagramClient Receiver task finished");
+        Ok(())
+    }
+
+    #[instrument(level = "debug", skip(socket, hub_receiver, router, filter, stats))]
+    async fn unix_send_task(
+        socket: Arc<UnixDatagram>,
+        server_addr: &str,
+        mut hub_receiver: broadcast::Receiver<Protocol>,
+        router: &Router,
+        filter: &Filter,
+        stats: &DriverStatsCounters,
+    ) -> Result<()> {
+        loop {
+            match hub_receiver.recv().await {
+                Ok(message) => {
+                    if message.origin.eq(server_addr) {
+                        continue; // Don't do loopback
+                    }
+
+                    if !router.should_forward(&message, server_addr) {
+                        continue; // Targeted to another link
+                    }
+
+                    if !filter.accepts_outgoing(&message) {
+                        continue;
+                    }
+
+                    match socket.send(message.raw_bytes()).await {
+                        Ok(_) => {
+                            stats.record_sent(message.raw_bytes().len());
+                        }
+                        Err(error) => {
+                            error!("Failed to send Unix datagram to {server_addr}: {error:?}");
+                            break;
+                        }
+                    }
+                }
+                Err(broadcast::error::RecvError::Closed) => {
+                    error!("Hub channel closed!");
+                    break;
+                }
+                Err(broadcast::error::RecvError::Lagged(count)) => {
+                    warn!("Channel lagged by {count} messages.");
+                    stats.record_lagged(count);
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+#[async_trait::async_trait]
+impl Driver for UnixDatagramClient {
+    #[instrument(level = "debug", skip(self, hub_sender, router))]
+    async fn run(
+        &self,
+        hub_sender: broadcast::Sender<Protocol>,
+        router: Arc<Router>,
+    ) -> Result<()> {
+        let server_path = &self.server_path;
+        let server_addr = server_path.display().to_string();
+        let mut first_connection = true;
+
+        loop {
+            // The client socket and its directory are removed with the guard
+            let (socket, _private_dir) = match Self::connect(server_path) {
+                Ok((socket, private_dir)) => (Arc::new(socket), private_dir),
+                Err(error) => {
+                    error!("Failed connecting UnixDatagramClient to {server_path:?}: {error:?}");
+                    tokio::time::sleep(tokio::time::Duration::from_secs(1)).await;
+                    continue;
+                }
+            };
+            debug!("UnixDatagramClient successfully connected to {server_path:?}");
+
+            if !first_connection {
+                self.stats.record_reconnection();
+            }
+            first_connection = false;
+            self.stats.add_peer(&server_addr);
+
+            let hub_sender = Arc::new(hub_sender.clone());
+            let hub_receiver = hub_sender.subscribe();
+
+            tokio::select! {
+                result = UnixDatagramClient::unix_receive_task(socket.clone(), &server_addr, hub_sender, &router, &self.filter, &self.stats) => {
+                    if let Err(error) = result {
+                        error!("Error in receiving Unix datagrams: {error:?}");
+                    }
+                }
+                result = UnixDatagramClient::unix_send_task(socket, &server_addr, hub_receiver, &router, &self.filter, &self.stats) => {
+                    if let Err(error) = result {
+                        error!("Error in sending Unix datagrams: {error:?}");
+                    }
+                }
+            }
+
+            self.stats.remove_peer(&server_addr);
+
+            tokio::time::sleep(tokio::time::Duration::from_secs(1)).await;
+        }
+    }
+
+    #[instrument(level = "debug", skip(self))]
+    fn info(&self) -> DriverInfo {
+        DriverInfo {
+            name: "UnixDatagramClient".to_string(),
+            endpoint: None,
+            filter: self.filter.clone(),
+        }
+    }
+
+    #[instrument(level = "debug", skip(self))]
+    fn stats(&self) -> DriverStats {
+        self.stats.snapshot()
+    }
+}
@@ -0,0 +1,275 @@
+use std::{
+    os::unix::fs::{DirBuilderExt, FileTypeExt, PermissionsExt},
+    path::{Path, PathBuf},
+    sync::atomic::{AtomicU64, Ordering},
+};
+
+use anyhow::{anyhow, Context, Result};
+use socket2::{Domain, SockAddr, Socket, Type};
+use tracing::*;
+
+pub mod client;
+pub mod datagram;
+pub mod server;
+
+/// Distinguishes the private directories created by this process
+static PRIVATE_DIR_COUNT: AtomicU64 = AtomicU64::new(0);
+
+/// Socket file bound by this process, removed when dropped (e.g. when the driver is removed)
+#[derive(Debug)]
+struct SocketFile {
+    path: PathBuf,
+}
+
+impl Drop for SocketFile {
+    fn drop(&mut self) {
+        match std::fs::remove_file(&self.path) {
+            Ok(()) => debug!("Socket file {:?} removed", self.path),
+            Err(error) if error.kind() == std::io::ErrorKind::NotFound => {}
+            Err(error) => warn!("Failed to remove socket file {:?}: {error:?}", self.path),
+        }
+    }
+}
+
+/// Directory only accessible by this user, removed with its content when dropped
+#[derive(Debug)]
+struct PrivateDir {
+    path: PathBuf,
+}
+
+impl PrivateDir {
+    /// Creates a new directory in `$XDG_RUNTIME_DIR`, or in the temporary directory when it isn't
+    /// set, failing instead of reusing a directory that already exists
+    #[instrument(level = "debug")]
+    fn create() -> Result<Self> {
+        let base = std::env::var_os("XDG_RUNTIME_DIR")
+            .map(PathBuf::from)
+            .filter(|path| path.is_dir())
+            .unwrap_or_else(std::env::temp_dir);
+
+        Self::create_in(&base, "mavlink-server")
+    }
+
+    /// Creates a new directory in the parent, named after the prefix and made unique to this
+    /// process, failing instead of reusing a directory that already exists
+    #[instrument(level = "debug")]
+    fn create_in(parent: &Path, prefix: &str) -> Result<Self> {
+        let nanos = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .unwrap_or_default()
+            .subsec_nanos();
+        let path = parent.join(format!(
+            "{prefix}-{}-{}-{nanos:08x}",
+            std::process::id(),
+            PRIVATE_DIR_COUNT.fetch_add(1, Ordering::Relaxed)
+        ));
+
+        std::fs::DirBuilder::new()
+            .mode(0o700)
+            .create(&path)
+            .context(format!("Failed creating private directory {path:?}"))?;
+
+        // Only built once the directory exists, so it never removes someone else's
+        Ok(Self { path })
+    }
+}
+
+impl Drop for PrivateDir {
+    fn drop(&mut self) {
+        if let Err(error) = std::fs::remove_dir_all(&self.path) {
+            warn!(
+                "Failed to remove private directory {:?}: {error:?}",
+                self.path
+            );
+        }
+    }
+}
+
+/// Checks if a socket of the given type is listening on the path
+fn is_in_use(path: &Path, socket_type: Type) -> bool {
+    let (Ok(socket), Ok(address)) = (
+        Socket::new(Domain::UNIX, socket_type, None),
+        SockAddr::unix(path),
+    ) else {
+        return false;
+    };
+
+    if socket.set_nonblocking(true).is_err() {
+        return false;
+    }
+
+    match socket.connect(&address) {
+        Ok(()) => true,
+        // A stream server that doesn't keep up with its connections is still there
+        Err(error) => error.kind() == std::io::ErrorKind::WouldBlock,
+    }
+}
+
+/// Removes the socket file left by a previous run, so it can be bound again, refusing to remove
+/// anything that isn't a socket or a socket that is still in use
+#[instrument(level = "debug")]
+fn remove_stale_socket(path: &Path) -> Result<()> {
+    match std::fs::symlink_metadata(path) {
+        Ok(metadata) if metadata.file_type().is_socket() => {
+            if is_in_use(path, Type::STREAM) || is_in_use(path, Type::DGRAM) {
+                return Err(anyhow!("{path:?} is in use by another process"));
+            }
+
+            debug!("Removing stale socket file {path:?}");
+            Ok(std::fs::remove_file(path)?)
+        }
+        Ok(_) => Err(anyhow!("{path:?} already exists and is not a socket")),
+        Err(error) if error.kind() == std::io::ErrorKind::NotFound => Ok(()),
+        Err(error) => Err(error.into()),
+    }
+}
+
+/// Binds a socket to the path, with the given permissions (e.g. 0o660) from the start: the
+/// socket is bound in a private directory next to the path, where its permissions are set, and
+/// then moved in place. The process umask applies when no permissions are given.
+#[instrument(level = "debug", skip(bind))]
+fn bind_socket<S>(
+    path: &Path,
+    mode: Option<u32>,
+    bind: impl FnOnce(PathBuf) -> std::io::Result<S>,
+) -> Result<(S, SocketFile)> {
+    remove_stale_socket(path)?;
+
+    let Some(mode) = mode else {
+        let socket = bind(path.to_path_buf())?;
+        return Ok((
+            socket,
+            SocketFile {
+                path: path.to_path_buf(),
+            },
+        ));
+    };
+
+    let parent = match path.parent() {
+        Some(parent) if !parent.as_os_str().is_empty() => parent,
+        _ => Path::new("."),
+    };
+    let file_name = path
+        .file_name()
+        .context("Socket path should have a file name")?;
+
+    let private_dir = PrivateDir::create_in(parent, &format!(".{}", file_name.to_string_lossy()))?;
+
+    let private_path = private_dir.path.join(file_name);
+    let socket = bind(private_path.clone())?;
+    std::fs::set_permissions(&private_path, std::fs::Permissions::from_mode(mode))?;
+    std::fs::rename(&private_path, path)?;
+
+    Ok((
+        socket,
+        SocketFile {
+            path: path.to_path_buf(),
+        },
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use mavlink::MavlinkVersion;
+    use tokio::{
+        io::AsyncWriteExt,
+        net::{UnixDatagram, UnixListener, UnixStream},
+        sync::broadcast,
+    };
+
+    use super::*;
+    use crate::{
+        drivers::{
+            filter::Filter, stats::DriverStatsCounters, tcp::tcp_receive_task,
+            udp::forward_datagram,
+        },
+        protocol::{fixtures::heartbeat_frame, Protocol},
+        router::Router,
+    };
+
+    fn test_path(name: &str) -> PathBuf {
+        std::env::temp_dir().join(format!("mavlink-server-test-{}-{name}", std::process::id()))
+    }
+
+    #[tokio::test]
+    async fn stream_messages_reach_the_hub() {
+        let (mut client, server) = UnixStream::pair().unwrap();
+        let (hub_sender, mut hub_receiver) = broadcast::channel::<Protocol>(16);
+        let stats = DriverStatsCounters::default();
+
+        let frame = heartbeat_frame(MavlinkVersion::V2, 0);
+        client.write_all(&frame).await.unwrap();
+        drop(client);
+
+        tcp_receive_task(
+            server,
+            "unix#1",
+            Arc::new(hub_sender),
+            &Router::default(),
+            &Filter::default(),
+            &stats,
+        )
+        .await
+        .unwrap();
+
+        let message = hub_receiver.try_recv().unwrap();
+        assert_eq!(message.origin, "unix#1");
+        assert_eq!(message.raw_bytes(), frame.as_slice());
+    }
+
+    #[tokio::test]
+    async fn datagram_messages_reach_the_hub() {
+        let (client, server) = UnixDatagram::pair().unwrap();
+        let (hub_sender, mut hub_receiver) = broadcast::channel::<Protocol>(16);
+        let stats = DriverStatsCounters::default();
+
+        let frame = heartbeat_frame(MavlinkVersion::V2, 0);
+        client.send(&frame).await.unwrap();
+
+        let mut buf = Vec::with_capacity(1024);
+        let bytes_received = server.recv_buf(&mut buf).await.unwrap();
+        forward_datagram(
+            &buf[..bytes_received],
+            "/run/client.sock",
+            &hub_sender,
+            &Router::default(),
+            &Filter::default(),
+            &stats,
+        )
+        .await;
+
+        let message = hub_receiver.try_recv().unwrap();
+        assert_eq!(message.origin, "/run/client.sock");
+        assert_eq!(message.raw_bytes(), frame.as_slice());
+    }
+
+    #[tokio::test]
+    async fn sockets_in_use_are_not_replaced() {
+        let path = test_path("in-use.sock");
+        let _ = std::fs::remove_file(&path);
+
+        let (listener, socket_file) = bind_socket(&path, None, UnixListener::bind).unwrap();
+        assert!(bind_socket(&path, None, UnixListener::bind).is_err());
+        assert!(bind_socket(&path, None, UnixDatagram::bind).is_err());
+
+        drop(listener);
+        drop(socket_file);
+        assert!(!path.exists());
+    }
+
+    #[tokio::test]
+    async fn sockets_are_bound_with_their_mode() {
+        let path = test_path("mode.sock");
+        let _ = std::fs::remove_file(&path);
+
+        // A stale socket, left by a listener that is gone, is replaced
+        drop(std::os::unix::net::UnixListener::bind(&path).unwrap());
+
+        let (_socket, _socket_file) = bind_socket(&path, Some(0o600), UnixDatagram::bind).unwrap();
+        let metadata = std::fs::symlink_metadata(&path).unwrap();
+        assert!(metadata.file_type().is_socket());
+        assert_eq!(metadata.permissions().mode() & 0o7777, 0o600);
+    }
+}
@@ -0,0 +1,132 @@
+use std::{path::PathBuf, sync::Arc};
+
+use crate::drivers::tcp::{tcp_receive_task, tcp_send_task};
+use crate::{protocol::Protocol, router::Router};
+use anyhow::Result;
+use tokio::net::{UnixListener, UnixStream};
+use tokio::sync::broadcast;
+use tokio::task::JoinSet;
+use tracing::*;
+
+use crate::drivers::{
+    filter::Filter,
+    stats::{DriverStats, DriverStatsCounters},
+    unix::bind_socket,
+    Driver, DriverInfo,
+};
+
+/// Accepts local clients on a Unix stream socket, framed the same way as TCP
+pub struct UnixServer {
+    pub path: PathBuf,
+    pub filter: Filter,
+    /// Permissions of the socket file, e.g. 0o660
+    pub mode: Option<u32>,
+    stats: Arc<DriverStatsCounters>,
+}
+
+impl UnixServer {
+    #[instrument(level = "debug")]
+    pub fn new(path: &str, filter: Filter, mode: Option<u32>) -> Self {
+        Self {
+            path: PathBuf::from(path),
+            filter,
+            mode,
+            stats: Arc::new(DriverStatsCounters::default()),
+        }
+    }
+
+    /// Handles communication with a single client
+    #[instrument(level = "debug", skip(socket, hub_sender, router, filter, stats))]
+    async fn handle_client(
+        socket: UnixStream,
+        client_name: String,
+        hub_sender: Arc<broadcast::Sender<Protocol>>,
+        router: Arc<Router>,
+        filter: Filter,
+        stats: Arc<DriverStatsCounters>,
+    ) -> Result<()> {
+        // Each task owns its half, so sending never waits on a pending read
+        let (reader, writer) = tokio::io::split(socket);
+        let hub_receiver = hub_sender.subscribe();
+        stats.add_peer(&client_name);
+
+        tokio::select! {
+            result = tcp_receive_task(reader, &client_name, hub_sender, &router, &filter, &stats) => {
+                if let Err(e) = result {
+                    error!("Error in Unix receive task for {client_name}: {e:?}");
+                }
+            }
+            result = tcp_send_task(writer, &client_name, hub_receiver, &router, &filter, &stats) => {
+                if let Err(e) = result {
+                    error!("Error in Unix send task for {client_name}: {e:?}");
+                }
+            }
+        }
+
+        stats.remove_peer(&client_name);
+
+        debug!("Finished handling connection with {client_name}");
+        Ok(())
+    }
+}
+
+#[async_trait::async_trait]
+impl Driver for UnixServer {
+    #[instrument(level = "debug", skip(self, hub_sender, router))]
+    async fn run(
+        &self,
+        hub_sender: broadcast::Sender<Protocol>,
+        router: Arc<Router>,
+    ) -> Result<()> {
+        // The socket file is removed with the guard, when the driver stops
+        let (listener, _socket_file) = bind_socket(&self.path, self.mode, UnixListener::bind)?;
+
+        let hub_sender = Arc::new(hub_sender);
+
+        // Client tasks are aborted when this set is dropped, so they don't outlive the driver
+        let mut clients = JoinSet::new();
+
+        // Clients connect from unnamed sockets, so each connection is named after its order
+        let mut connection_count: u64 = 0;
+
+        loop {
+            // Clean up finished clients
+            while clients.try_join_next().is_some() {}
+
+            match listener.accept().await {
+                Ok((socket, _)) => {
+                    connection_count += 1;
+                    let client_name = format!("{}#{connection_count}", self.path.display());
+                    let hub_sender_cloned = Arc::clone(&hub_sender);
+
+                    clients.spawn(UnixServer::handle_client(
+                        socket,
+                        client_name,
+                        hub_sender_cloned,
+                        router.clone(),
+                        self.filter.clone(),
+                        self.stats.clone(),
+                    ));
+                }
+                Err(error) => {
+                    error!("Failed to accept Unix connection: {error:?}");
+                    tokio::time::sleep(tokio::time::Duration::from_secs(1)).await;
+                }
+            }
+        }
+    }
+
+    #[instrument(level = "debug", skip(self))]
+    fn info(&self) -> DriverInfo {
+        DriverInfo {
+            name: "UnixServer".to_string(),
+            endpoint: None,
+            filter: self.filter.clone(),
+        }
+    }
+
+    #[instrument(level = "debug", skip(self))]
+    fn stats(&self) -> DriverStats {
+        self.stats.snapshot()
+    }
+}
@@ -0,0 +1,10 @@
+pub mod server;
+
+/// How MAVLink messages are carried in the WebSocket messages
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum WebSocketEncoding {
+    /// One raw MAVLink frame per binary message
+    Raw,
+    /// One decoded message per text message, see [`crate::protocol::MAVLinkJSON`]
+    Json,
+}
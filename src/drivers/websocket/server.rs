@@ -0,0 +1,325 @@
+use std::sync::Arc;
+
+use crate::{
+    drivers::decoder::StreamDecoder,
+    protocol::{MAVLinkJSON, MAVLinkMessageRaw, Protocol},
+    router::Router,
+};
+use anyhow::{anyhow, Context, Result};
+use futures::{
+    stream::{SplitSink, SplitStream},
+    SinkExt, StreamExt,
+};
+use tokio::net::{TcpListener, TcpStream};
+use tokio::sync::broadcast;
+use tokio::task::JoinSet;
+use tokio_tungstenite::{tungstenite::Message, WebSocketStream};
+use tracing::*;
+
+use crate::drivers::{
+    filter::Filter,
+    stats::{DriverStats, DriverStatsCounters},
+    websocket::WebSocketEncoding,
+    Driver, DriverInfo,
+};
+
+/// Accepts WebSocket clients (e.g. browser-based ground stations and dashboards), where each
+/// WebSocket message carries exactly one MAVLink message, in both directions
+pub struct WebSocketServer {
+    pub local_addr: String,
+    pub filter: Filter,
+    pub encoding: WebSocketEncoding,
+    stats: Arc<DriverStatsCounters>,
+}
+
+impl WebSocketServer {
+    #[instrument(level = "debug")]
+    pub fn new(local_addr: &str, filter: Filter, encoding: WebSocketEncoding) -> Self {
+        Self {
+            local_addr: local_addr.to_string(),
+            filter,
+            encoding,
+            stats: Arc::new(DriverStatsCounters::default()),
+        }
+    }
+
+    /// Decodes a WebSocket message into a MAVLink frame, `None` for messages without one
+    async fn decode(
+        message: Message,
+        encoding: WebSocketEncoding,
+    ) -> Result<Option<MAVLinkMessageRaw>> {
+        match (message, encoding) {
+            (Message::Binary(bytes), WebSocketEncoding::Raw) => {
+                // Each message is exactly one frame, so nothing is kept between messages
+                let mut decoder = StreamDecoder::new();
+                decoder.push(&bytes);
+
+                let frame = decoder
+                    .next_frame()
+                    .await
+                    .context("Invalid MAVLink frame")?;
+                if decoder.take_bytes_discarded() > 0 {
+                    return Err(anyhow!("Unexpected bytes before the MAVLink frame"));
+                }
+                if frame.raw_bytes().len() != bytes.len() {
+                    return Err(anyhow!("Unexpected bytes after the MAVLink frame"));
+                }
+
+                Ok(Some(frame))
+            }
+            (Message::Text(text), WebSocketEncoding::Json) => {
+                let message: MAVLinkJSON =
+                    serde_json::from_str(&text).context("Invalid MAVLink JSON message")?;
+
+                Ok(Some(message.to_raw()))
+            }
+            (Message::Binary(_) | Message::Text(_), encoding) => {
+                Err(anyhow!("Unexpected message type for {encoding:?} encoding"))
+            }
+            // Pings are answered by the WebSocket itself
+            _ => Ok(None),
+        }
+    }
+
+    /// Encodes a MAVLink frame into a WebSocket message
+    fn encode(message: &Protocol, encoding: WebSocketEncoding) -> Result<Message> {
+        match encoding {
+            WebSocketEncoding::Raw => Ok(Message::Binary(message.raw_bytes().to_vec())),
+            WebSocketEncoding::Json => Ok(Message::Text(serde_json::to_string(
+                &MAVLinkJSON::try_from(message)?,
+            )?)),
+        }
+    }
+
+    /// Handles communication with a single client, from the WebSocket handshake on
+    #[instrument(level = "debug", skip(socket, hub_sender, router, filter, stats))]
+    async fn handle_client(
+        socket: TcpStream,
+        remote_addr: String,
+        hub_sender: Arc<broadcast::Sender<Protocol>>,
+        router: Arc<Router>,
+        filter: Filter,
+        encoding: WebSocketEncoding,
+        stats: Arc<DriverStatsCounters>,
+    ) -> Result<()> {
+        let websocket = match tokio_tungstenite::accept_async(socket).await {
+            Ok(websocket) => websocket,
+            Err(error) => {
+                warn!("Failed WebSocket handshake with {remote_addr}: {error:?}");
+                return Ok(());
+            }
+        };
+        let (ws_sender, ws_receiver) = websocket.split();
+
+        let hub_receiver = hub_sender.subscribe();
+        stats.add_peer(&remote_addr);
+
+        tokio::select! {
+            result = Self::ws_receive_task(ws_receiver, &remote_addr, hub_sender, &router, &filter, encoding, &stats) => {
+                if let Err(e) = result {
+                    error!("Error in WebSocket receive task for {remote_addr}: {e:?}");
+                }
+            }
+            result = Self::ws_send_task(ws_sender, &remote_addr, hub_receiver, &router, &filter, encoding, &stats) => {
+                if let Err(e) = result {
+                    error!("Error in WebSocket send task for {remote_addr}: {e:?}");
+                }
+            }
+        }
+
+        stats.remove_peer(&remote_addr);
+
+        debug!("Finished handling WebSocket connection with {remote_addr}");
+        Ok(())
+    }
+
+    /// Receives messages from the WebSocket and sends them to the HUB Channel
+    #[instrument(level = "debug", skip(ws_receiver, hub_sender, router, filter, stats))]
+    async fn ws_receive_task(
+        mut ws_receiver: SplitStream<WebSocketStream<TcpStream>>,
+        remote_addr: &str,
+        hub_sender: Arc<broadcast::Sender<Protocol>>,
+        router: &Router,
+        filter: &Filter,
+        encoding: WebSocketEncoding,
+        stats: &DriverStatsCounters,
+    ) -> Result<()> {
+        while let Some(message) = ws_receiver.next().await {
+            let message = message?;
+            if let Message::Close(_) = message {
+                warn!("WebSocket connection closed by {remote_addr}.");
+                break;
+            }
+
+            let message = match Self::decode(message, encoding).await {
+                Ok(Some(message)) => message,
+                Ok(None) => continue,
+                Err(error) => {
+                    warn!("Ignoring message from {remote_addr}: {error:#}");
+                    stats.record_parse_errors(1);
+                    continue;
+                }
+            };
+            stats.record_received(message.raw_bytes().len());
+
+            if !filter.accepts_incoming(&message) {
+                continue;
+            }
+
+            let message = Protocol::new(remote_addr, message);
+
+            trace!("Received WebSocket message: {message:?}");
+            if let Err(error) = router.send_to_hub(&hub_sender, message) {
+                error!("Failed to send message to hub: {error:?}");
+            }
+        }
+
+        debug!("WebSocket Receive task for {remote_addr} finished");
+        Ok(())
+    }
+
+    /// Receives messages from the HUB Channel and sends them to the WebSocket
+    #[instrument(level = "debug", skip(ws_sender, hub_receiver, router, filter, stats))]
+    async fn ws_send_task(
+        mut ws_sender: SplitSink<WebSocketStream<TcpStream>, Message>,
+        remote_addr: &str,
+        mut hub_receiver: broadcast::Receiver<Protocol>,
+        router: &Router,
+        filter: &Filter,
+        encoding: WebSocketEncoding,
+        stats: &DriverStatsCounters,
+    ) -> Result<()> {
+        loop {
+            let message = match hub_receiver.recv().await {
+                Ok(message) => message,
+                Err(broadcast::error::RecvError::Closed) => {
+                    error!("Hub channel closed!");
+                    break;
+                }
+                Err(broadcast::error::RecvError::Lagged(count)) => {
+                    warn!("Channel lagged by {count} messages.");
+                    stats.record_lagged(count);
+                    continue;
+                }
+            };
+
+            if message.origin.eq(&remote_addr) {
+                continue; // Don't do loopback
+            }
+
+            if !router.should_forward(&message, remote_addr) {
+                continue; // Targeted to another link
+            }
+
+            if !filter.accepts_outgoing(&message) {
+                continue;
+            }
+
+            let ws_message = match Self::encode(&message, encoding) {
+                Ok(ws_message) => ws_message,
+                Err(error) => {
+                    trace!("Failed to encode message for {remote_addr}: {error:#}");
+                    continue;
+                }
+            };
+
+            ws_sender.send(ws_message).await?;
+            stats.record_sent(message.raw_bytes().len());
+
+            trace!("Message sent to {remote_addr} from WebSocket server: {message:?}");
+        }
+
+        debug!("WebSocket Send task for {remote_addr} finished");
+        Ok(())
+    }
+}
+
+#[async_trait::async_trait]
+impl Driver for WebSocketServer {
+    #[instrument(level = "debug", skip(self, hub_sender, router))]
+    async fn run(
+        &self,
+        hub_sender: broadcast::Sender<Protocol>,
+        router: Arc<Router>,
+    ) -> Result<()> {
+        let listener = TcpListener::bind(&self.local_addr).await?;
+        let hub_sender = Arc::new(hub_sender);
+
+        // Client tasks are aborted when this set is dropped, so they don't outlive the driver
+        let mut clients = JoinSet::new();
+
+        loop {
+            // Clean up finished clients
+            while clients.try_join_next().is_some() {}
+
+            match listener.accept().await {
+                Ok((socket, remote_addr)) => {
+                    let remote_addr = remote_addr.to_string();
+                    let hub_sender_cloned = Arc::clone(&hub_sender);
+
+                    clients.spawn(WebSocketServer::handle_client(
+                        socket,
+                        remote_addr,
+                        hub_sender_cloned,
+                        router.clone(),
+                        self.filter.clone(),
+                        self.encoding,
+                        self.stats.clone(),
+                    ));
+                }
+                Err(error) => {
+                    error!("Failed to accept WebSocket connection: {error:?}");
+                    tokio::time::sleep(tokio::time::Duration::from_secs(1)).await;
+                }
+            }
+        }
+    }
+
+    #[instrument(level = "debug", skip(self))]
+    fn info(&self) -> DriverInfo {
+        let name = match self.encoding {
+            WebSocketEncoding::Raw => "WebSocketServer",
+            WebSocketEncoding::Json => "WebSocketJsonServer",
+        };
+
+        DriverInfo {
+            name: name.to_string(),
+            endpoint: None,
+            filter: self.filter.clone(),
+        }
+    }
+
+    #[instrument(level = "debug", skip(self))]
+    fn stats(&self) -> DriverStats {
+        self.stats.snapshot()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use mavlink::MavlinkVersion;
+
+    use super::*;
+    use crate::protocol::fixtures::heartbeat_frame;
+
+    async fn decode_raw(bytes: Vec<u8>) -> Result<Option<MAVLinkMessageRaw>> {
+        WebSocketServer::decode(Message::Binary(bytes), WebSocketEncoding::Raw).await
+    }
+
+    #[tokio::test]
+    async fn raw_messages_are_exactly_one_frame() {
+        let frame = heartbeat_frame(MavlinkVersion::V2, 0);
+        let message = decode_raw(frame.clone()).await.unwrap().unwrap();
+        assert_eq!(message.raw_bytes(), frame.as_slice());
+
+        let mut prefixed = vec![0x00, 0x42];
+        prefixed.extend_from_slice(&frame);
+        assert!(decode_raw(prefixed).await.is_err());
+
+        let mut two_frames = frame.clone();
+        two_frames.extend_from_slice(&heartbeat_frame(MavlinkVersion::V2, 1));
+        assert!(decode_raw(two_frames).await.is_err());
+
+        assert!(decode_raw(frame[..frame.len() - 1].to_vec()).await.is_err());
+    }
+}
@@ -1,20 +1,86 @@
-use std::{collections::HashMap, ops::Div, sync::Arc};
+use std::{
+    collections::HashMap,
+    ops::Div,
+    sync::{
+        atomic::{AtomicU8, Ordering},
+        Arc,
+    },
+};
 
-use crate::protocol::Protocol;
+use crate::{cli, drivers, protocol::Protocol, router::Router};
 use anyhow::{anyhow, Context, Result};
-use mavlink::MAVLinkV2MessageRaw;
-use tokio::sync::{broadcast, RwLock};
+use mavlink::{
+    ardupilotmega::{MavAutopilot, MavType},
+    MAVLinkV2MessageRaw,
+};
+use serde::{Deserialize, Serialize};
+use tokio::sync::{broadcast, Mutex, RwLock};
 use tracing::*;
 
-use crate::drivers::{Driver, DriverInfo};
+use crate::drivers::{stats::DriverStats, Driver, DriverInfo};
+
+struct DriverHandle {
+    driver: Arc<dyn Driver>,
+    /// Endpoint the driver was created from, if any
+    endpoint: Option<String>,
+    /// Created from the configuration, so it is kept or removed when the configuration is reloaded
+    configured: bool,
+    task: tokio::task::JoinHandle<Result<()>>,
+}
+
+/// Statistics of the messages that went through the hub
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct HubStats {
+    pub messages_routed: u64,
+    /// Messages dropped from the routing task because it couldn't keep up, which are missing from
+    /// these statistics and from the latest messages
+    pub lagged_messages: u64,
+    pub messages_per_msgid: HashMap<u32, u64>,
+}
+
+/// Identity of the hub and content of its HEARTBEAT
+#[derive(Debug, Clone, Serialize)]
+pub struct HubSettings {
+    pub system_id: u8,
+    pub component_id: u8,
+    /// Frequency, in Hz, of the HEARTBEAT, 0 means it is disabled
+    pub heartbeat_frequency: f32,
+    pub mavtype: String,
+    pub autopilot: String,
+}
+
+/// Changes to the [`HubSettings`], where the missing fields are kept unchanged
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct HubSettingsUpdate {
+    pub system_id: Option<u8>,
+    pub component_id: Option<u8>,
+    pub heartbeat_frequency: Option<f32>,
+    pub mavtype: Option<String>,
+    pub autopilot: Option<String>,
+}
 
 pub struct Hub {
-    drivers: Arc<RwLock<HashMap<u64, Arc<dyn Driver>>>>,
+    drivers: Arc<RwLock<HashMap<u64, DriverHandle>>>,
+    /// Serializes the changes to the drivers, so a configuration reload doesn't interleave with
+    /// drivers being added or removed from the REST API
+    changes: Mutex<()>,
     bcst_sender: broadcast::Sender<Protocol>,
     last_driver_id: Arc<RwLock<u64>>,
     component_id: Arc<RwLock<u8>>,
     system_id: Arc<RwLock<u8>>,
+    frequency: Arc<RwLock<f32>>,
+    mavtype: Arc<RwLock<MavType>>,
+    autopilot: Arc<RwLock<MavAutopilot>>,
+    /// Sequence of the messages originated by the hub
+    sequence: Arc<AtomicU8>,
+    stats: Arc<std::sync::RwLock<HubStats>>,
+    /// Latest message of each (sysid, compid, msgid) that went through the hub
+    latest_messages: Arc<std::sync::RwLock<HashMap<(u8, u8, u32), Protocol>>>,
+    /// Routing table shared with the drivers
+    router: Arc<Router>,
     task: tokio::task::JoinHandle<Result<()>>,
+    routing_task: tokio::task::JoinHandle<Result<()>>,
 }
 
 impl Hub {
@@ -24,58 +90,150 @@ impl Hub {
         component_id: Arc<RwLock<u8>>,
         system_id: Arc<RwLock<u8>>,
         frequency: Arc<RwLock<f32>>,
+        mavtype: Arc<RwLock<MavType>>,
+        autopilot: Arc<RwLock<MavAutopilot>>,
     ) -> Self {
         let (bcst_sender, _) = broadcast::channel(buffer_size);
 
+        let sequence = Arc::new(AtomicU8::new(0));
+
         let bcst_sender_cloned = bcst_sender.clone();
         let component_id_cloned = component_id.clone();
         let system_id_cloned = system_id.clone();
         let frequency_cloned = frequency.clone();
+        let mavtype_cloned = mavtype.clone();
+        let autopilot_cloned = autopilot.clone();
+        let sequence_cloned = sequence.clone();
         let task = tokio::spawn(async move {
             Self::heartbeat_task(
                 bcst_sender_cloned,
-                component_id_cloned,
                 system_id_cloned,
+                component_id_cloned,
                 frequency_cloned,
+                mavtype_cloned,
+                autopilot_cloned,
+                sequence_cloned,
             )
             .await
         });
 
+        let stats = Arc::new(std::sync::RwLock::new(HubStats::default()));
+        let latest_messages = Arc::new(std::sync::RwLock::new(HashMap::new()));
+        let router = Arc::new(Router::default());
+
+        let bcst_receiver = bcst_sender.subscribe();
+        let stats_cloned = stats.clone();
+        let latest_messages_cloned = latest_messages.clone();
+        let routing_task = tokio::spawn(async move {
+            Self::routing_task(bcst_receiver, stats_cloned, latest_messages_cloned).await
+        });
+
         Self {
             drivers: Arc::new(RwLock::new(HashMap::new())),
+            changes: Mutex::new(()),
             bcst_sender,
             last_driver_id: Arc::new(RwLock::new(0)),
             component_id,
             system_id,
+            frequency,
+            mavtype,
+            autopilot,
+            sequence,
+            stats,
+            latest_messages,
+            router,
             task,
+            routing_task,
         }
     }
 
+    /// Adds a driver created outside of the configuration (e.g. from the REST API), remembering
+    /// the endpoint it was created from
     #[instrument(level = "debug", skip(self, driver))]
-    pub async fn add_driver(&self, driver: Arc<dyn Driver>) -> Result<u64> {
+    pub async fn add_driver(&self, driver: Arc<dyn Driver>, endpoint: &str) -> Result<u64> {
+        let _changes = self.changes.lock().await;
+        self.insert_driver(driver, Some(endpoint.to_string()), false)
+            .await
+    }
+
+    /// Creates and adds the driver of a configured endpoint, remembering the endpoint so the
+    /// driver can be kept or removed when the configuration is reloaded
+    #[instrument(level = "debug", skip(self))]
+    pub async fn add_endpoint(&self, endpoint: &str) -> Result<u64> {
+        let driver = drivers::create_driver(endpoint)?;
+
+        let _changes = self.changes.lock().await;
+        self.insert_driver(driver, Some(endpoint.to_string()), true)
+            .await
+    }
+
+    async fn insert_driver(
+        &self,
+        driver: Arc<dyn Driver>,
+        endpoint: Option<String>,
+        configured: bool,
+    ) -> Result<u64> {
         let mut last_id = self.last_driver_id.write().await;
         let id = *last_id;
         *last_id += 1;
 
         let mut drivers = self.drivers.write().await;
 
-        if drivers.insert(id, driver.clone()).is_some() {
+        if drivers.contains_key(&id) {
             return Err(anyhow!(
                 "Failed addinng driver: id {id:?} is already present"
             ));
         }
 
         let hub_sender = self.bcst_sender.clone();
+        let router = self.router.clone();
+        let driver_cloned = driver.clone();
+        let task = tokio::spawn(async move {
+            let result = driver_cloned.run(hub_sender, router).await;
+            match &result {
+                Ok(()) => info!("Driver id {id:?} finished"),
+                Err(error) => error!("Driver id {id:?} failed: {error:?}"),
+            }
+            result
+        });
 
-        tokio::spawn(async move { driver.run(hub_sender).await });
+        drivers.insert(
+            id,
+            DriverHandle {
+                driver,
+                endpoint,
+                configured,
+                task,
+            },
+        );
 
         Ok(id)
     }
 
+    /// Stops the driver task, closing its sockets, and removes it from the hub
     #[instrument(level = "debug", skip(self))]
     pub async fn remove_driver(&self, id: u64) -> Result<()> {
-        let mut drivers = self.drivers.write().await;
-        drivers.remove(&id).context("Driver id {id:?} not found")?;
+        let _changes = self.changes.lock().await;
+        self.stop_driver(id).await
+    }
+
+    async fn stop_driver(&self, id: u64) -> Result<()> {
+        let handle = self
+            .drivers
+            .write()
+            .await
+            .remove(&id)
+            .context(format!("Driver id {id:?} not found"))?;
+
+        handle.task.abort();
+
+        match handle.task.await {
+            Ok(Ok(())) => debug!("Driver id {id:?} had already finished"),
+            Ok(Err(error)) => warn!("Driver id {id:?} had already failed: {error:?}"),
+            Err(error) if error.is_cancelled() => debug!("Driver id {id:?} stopped"),
+            Err(error) => error!("Driver id {id:?} panicked: {error:?}"),
+        }
+
         Ok(())
     }
 
@@ -84,31 +242,186 @@ impl Hub {
         let drivers = self.drivers.read().await;
         drivers
             .iter()
-            .map(|(&id, driver)| (id, driver.info()))
+            .map(|(&id, handle)| {
+                let mut info = handle.driver.info();
+                info.endpoint.clone_from(&handle.endpoint);
+                (id, info)
+            })
+            .collect()
+    }
+
+    /// Endpoints of the drivers created from the configuration
+    #[instrument(level = "debug", skip(self))]
+    pub async fn endpoints(&self) -> HashMap<u64, String> {
+        let drivers = self.drivers.read().await;
+        drivers
+            .iter()
+            .filter(|(_, handle)| handle.configured)
+            .filter_map(|(&id, handle)| Some((id, handle.endpoint.clone()?)))
             .collect()
     }
 
+    /// Brings the configured drivers in line with the given endpoints, removing the drivers of
+    /// endpoints that are gone and adding the new ones, while the unchanged drivers keep running.
+    /// Nothing is changed if any of the new endpoints is invalid.
+    #[instrument(level = "debug", skip(self))]
+    pub async fn update_endpoints(&self, endpoints: &[String]) -> Result<()> {
+        let _changes = self.changes.lock().await;
+
+        let mut new_endpoints: Vec<&String> = endpoints.iter().collect();
+        let mut removed_ids = vec![];
+
+        let mut current_endpoints: Vec<(u64, String)> =
+            self.endpoints().await.into_iter().collect();
+        current_endpoints.sort();
+
+        for (id, endpoint) in current_endpoints {
+            match new_endpoints.iter().position(|&new| new == &endpoint) {
+                Some(position) => {
+                    new_endpoints.remove(position);
+                }
+                None => removed_ids.push((id, endpoint)),
+            }
+        }
+
+        let mut new_drivers = vec![];
+        let mut errors = vec![];
+        for endpoint in new_endpoints {
+            match drivers::create_driver(endpoint) {
+                Ok(driver) => new_drivers.push((endpoint, driver)),
+                Err(error) => errors.push(format!("{endpoint:?}: {error:#}")),
+            }
+        }
+        if !errors.is_empty() {
+            return Err(anyhow!("Invalid endpoints: {}", errors.join(", ")));
+        }
+
+        for (id, endpoint) in removed_ids {
+            info!("Removing driver id {id:?} for {endpoint:?}");
+            if let Err(error) = self.stop_driver(id).await {
+                warn!("Failed removing driver id {id:?} for {endpoint:?}: {error:?}");
+            }
+        }
+
+        for (endpoint, driver) in new_drivers {
+            match self
+                .insert_driver(driver, Some(endpoint.to_string()), true)
+                .await
+            {
+                Ok(id) => info!("Driver id {id:?} created for {endpoint:?}"),
+                Err(error) => error!("Failed adding driver for {endpoint:?}: {error:?}"),
+            }
+        }
+
+        Ok(())
+    }
+
+    #[instrument(level = "debug", skip(self))]
+    pub async fn stats(&self) -> HashMap<u64, DriverStats> {
+        let drivers = self.drivers.read().await;
+        drivers
+            .iter()
+            .map(|(&id, handle)| (id, handle.driver.stats()))
+            .collect()
+    }
+
+    #[instrument(level = "debug", skip(self))]
+    pub fn hub_stats(&self) -> HubStats {
+        self.stats.read().unwrap().clone()
+    }
+
+    /// Latest message with the given msgid from the given (sysid, compid), if any was seen
+    #[instrument(level = "debug", skip(self))]
+    pub fn latest_message(
+        &self,
+        system_id: u8,
+        component_id: u8,
+        message_id: u32,
+    ) -> Option<Protocol> {
+        self.latest_messages
+            .read()
+            .unwrap()
+            .get(&(system_id, component_id, message_id))
+            .cloned()
+    }
+
+    /// Sequence number for the next message originated by the hub
+    #[instrument(level = "debug", skip(self))]
+    pub fn next_sequence(&self) -> u8 {
+        self.sequence.fetch_add(1, Ordering::Relaxed)
+    }
+
+    #[instrument(level = "debug", skip(self))]
+    pub async fn settings(&self) -> HubSettings {
+        HubSettings {
+            system_id: *self.system_id.read().await,
+            component_id: *self.component_id.read().await,
+            heartbeat_frequency: *self.frequency.read().await,
+            mavtype: format!("{:?}", *self.mavtype.read().await),
+            autopilot: format!("{:?}", *self.autopilot.read().await),
+        }
+    }
+
+    /// Changes the identity of the hub and the content of its HEARTBEAT, which take effect from
+    /// the next HEARTBEAT on
+    #[instrument(level = "debug", skip(self))]
+    pub async fn update_settings(&self, update: HubSettingsUpdate) -> Result<()> {
+        // Everything is validated before anything is changed
+        let mavtype = update
+            .mavtype
+            .map(|name| cli::mavlink_enum_parser::<MavType>(&name))
+            .transpose()
+            .map_err(|error| anyhow!(error))?;
+        let autopilot = update
+            .autopilot
+            .map(|name| cli::mavlink_enum_parser::<MavAutopilot>(&name))
+            .transpose()
+            .map_err(|error| anyhow!(error))?;
+        if let Some(frequency) = update.heartbeat_frequency {
+            if frequency < 0. || !frequency.is_finite() {
+                return Err(anyhow!(
+                    "Invalid heartbeat frequency: {frequency}, it should be a positive number, or 0 to disable it"
+                ));
+            }
+        }
+
+        if let Some(system_id) = update.system_id {
+            *self.system_id.write().await = system_id;
+        }
+        if let Some(component_id) = update.component_id {
+            *self.component_id.write().await = component_id;
+        }
+        if let Some(frequency) = update.heartbeat_frequency {
+            *self.frequency.write().await = frequency;
+        }
+        if let Some(mavtype) = mavtype {
+            *self.mavtype.write().await = mavtype;
+        }
+        if let Some(autopilot) = autopilot {
+            *self.autopilot.write().await = autopilot;
+        }
+
+        Ok(())
+    }
+
     async fn heartbeat_task(
         bcst_sender: broadcast::Sender<Protocol>,
         system_id: Arc<RwLock<u8>>,
         component_id: Arc<RwLock<u8>>,
         frequency: Arc<RwLock<f32>>,
+        mavtype: Arc<RwLock<MavType>>,
+        autopilot: Arc<RwLock<MavAutopilot>>,
+        sequence: Arc<AtomicU8>,
     ) -> Result<()> {
-        let message =
-            mavlink::ardupilotmega::MavMessage::HEARTBEAT(mavlink::ardupilotmega::HEARTBEAT_DATA {
-                custom_mode: 0,
-                mavtype: mavlink::ardupilotmega::MavType::MAV_TYPE_ONBOARD_CONTROLLER, // or MAV_TYPE_ONBOARD_GENERIC
-                autopilot: mavlink::ardupilotmega::MavAutopilot::MAV_AUTOPILOT_INVALID, // or MAV_AUTOPILOT_GENERIC?
-                base_mode: mavlink::ardupilotmega::MavModeFlag::empty(),
-                system_status: mavlink::ardupilotmega::MavState::MAV_STATE_STANDBY,
-                mavlink_version: 0x3,
-            });
-
         loop {
-            tokio::time::sleep(tokio::time::Duration::from_secs_f32(
-                1f32.div(*frequency.read().await),
-            ))
-            .await;
+            let frequency = *frequency.read().await;
+            if frequency <= 0. {
+                // Disabled, but it can be enabled at runtime
+                tokio::time::sleep(tokio::time::Duration::from_secs(1)).await;
+                continue;
+            }
+
+            tokio::time::sleep(tokio::time::Duration::from_secs_f32(1f32.div(frequency))).await;
 
             if bcst_sender.receiver_count().eq(&0) {
                 continue; // Don't try to send if the channel has no subscribers yet
@@ -117,11 +430,23 @@ impl Hub {
             let header = mavlink::MavHeader {
                 system_id: *system_id.read().await,
                 component_id: *component_id.read().await,
-                ..Default::default()
+                sequence: sequence.fetch_add(1, Ordering::Relaxed),
             };
 
-            let mut message_raw = Protocol::new("", MAVLinkV2MessageRaw::new());
+            let message = mavlink::ardupilotmega::MavMessage::HEARTBEAT(
+                mavlink::ardupilotmega::HEARTBEAT_DATA {
+                    custom_mode: 0,
+                    mavtype: *mavtype.read().await,
+                    autopilot: *autopilot.read().await,
+                    base_mode: mavlink::ardupilotmega::MavModeFlag::empty(),
+                    system_status: mavlink::ardupilotmega::MavState::MAV_STATE_STANDBY,
+                    mavlink_version: 0x3,
+                },
+            );
+
+            let mut message_raw = MAVLinkV2MessageRaw::new();
             message_raw.serialize_message(header, &message);
+            let message_raw = Protocol::new("", message_raw);
 
             if let Err(error) = bcst_sender.send(message_raw) {
                 error!("Failed to send HEARTBEAT message: {error}");
@@ -129,8 +454,184 @@ impl Hub {
         }
     }
 
+    /// Counts the messages that went through the hub, keeping the latest message of each kind
+    async fn routing_task(
+        mut bcst_receiver: broadcast::Receiver<Protocol>,
+        stats: Arc<std::sync::RwLock<HubStats>>,
+        latest_messages: Arc<std::sync::RwLock<HashMap<(u8, u8, u32), Protocol>>>,
+    ) -> Result<()> {
+        loop {
+            match bcst_receiver.recv().await {
+                Ok(message) => {
+                    let mut stats = stats.write().unwrap();
+                    stats.messages_routed += 1;
+                    *stats
+                        .messages_per_msgid
+                        .entry(message.message_id())
+                        .or_default() += 1;
+
+                    latest_messages.write().unwrap().insert(
+                        (
+                            message.system_id(),
+                            message.component_id(),
+                            message.message_id(),
+                        ),
+                        message,
+                    );
+                }
+                Err(broadcast::error::RecvError::Closed) => {
+                    error!("Hub channel closed!");
+                    break;
+                }
+                Err(broadcast::error::RecvError::Lagged(count)) => {
+                    warn!("Routing task lagged by {count} messages, they are missing from the hub statistics and the latest messages.");
+                    stats.write().unwrap().lagged_messages += count;
+                }
+            }
+        }
+
+        Ok(())
+    }
+
     #[instrument(level = "debug", skip(self))]
     pub fn get_sender(&self) -> broadcast::Sender<Protocol> {
         self.bcst_sender.clone()
     }
+
+    #[instrument(level = "debug", skip(self))]
+    pub fn router(&self) -> Arc<Router> {
+        self.router.clone()
+    }
+}
+
+impl Drop for Hub {
+    fn drop(&mut self) {
+        self.task.abort();
+        self.routing_task.abort();
+
+        if let Ok(drivers) = self.drivers.try_read() {
+            for handle in drivers.values() {
+                handle.task.abort();
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use mavlink::{ardupilotmega::MavMessage, MavlinkVersion, Message};
+
+    use super::*;
+
+    async fn hub(system_id: u8, component_id: u8) -> Hub {
+        Hub::new(
+            100,
+            Arc::new(RwLock::new(component_id)),
+            Arc::new(RwLock::new(system_id)),
+            Arc::new(RwLock::new(100.)),
+            Arc::new(RwLock::new(MavType::MAV_TYPE_ONBOARD_CONTROLLER)),
+            Arc::new(RwLock::new(MavAutopilot::MAV_AUTOPILOT_INVALID)),
+        )
+        .await
+    }
+
+    #[tokio::test]
+    async fn heartbeat_header_has_hub_ids() {
+        let hub = hub(1, 191).await;
+        let mut receiver = hub.get_sender().subscribe();
+
+        let message = receiver.recv().await.unwrap();
+
+        assert_eq!(message.version(), MavlinkVersion::V2);
+        assert_eq!(message.system_id(), 1);
+        assert_eq!(message.component_id(), 191);
+
+        let MavMessage::HEARTBEAT(heartbeat) =
+            MavMessage::parse(message.version(), message.message_id(), message.payload()).unwrap()
+        else {
+            panic!("Hub should only emit HEARTBEAT messages");
+        };
+        assert_eq!(heartbeat.mavtype, MavType::MAV_TYPE_ONBOARD_CONTROLLER);
+        assert_eq!(heartbeat.autopilot, MavAutopilot::MAV_AUTOPILOT_INVALID);
+    }
+
+    #[tokio::test]
+    async fn heartbeat_sequence_increments() {
+        let hub = hub(42, 1).await;
+        let mut receiver = hub.get_sender().subscribe();
+
+        let mut sequences = vec![];
+        for _ in 0..3 {
+            sequences.push(receiver.recv().await.unwrap().sequence());
+        }
+
+        assert_eq!(sequences[1], sequences[0].wrapping_add(1));
+        assert_eq!(sequences[2], sequences[1].wrapping_add(1));
+    }
+
+    #[tokio::test]
+    async fn heartbeat_follows_runtime_changes() {
+        let hub = hub(1, 191).await;
+        let mut receiver = hub.get_sender().subscribe();
+
+        *hub.system_id.write().await = 2;
+        *hub.component_id.write().await = 100;
+        *hub.mavtype.write().await = MavType::MAV_TYPE_GCS;
+
+        // Skips any heartbeat that could have been built before the change
+        receiver.recv().await.unwrap();
+        let message = receiver.recv().await.unwrap();
+
+        assert_eq!(message.system_id(), 2);
+        assert_eq!(message.component_id(), 100);
+
+        let MavMessage::HEARTBEAT(heartbeat) =
+            MavMessage::parse(message.version(), message.message_id(), message.payload()).unwrap()
+        else {
+            panic!("Hub should only emit HEARTBEAT messages");
+        };
+        assert_eq!(heartbeat.mavtype, MavType::MAV_TYPE_GCS);
+    }
+
+    #[tokio::test]
+    async fn invalid_endpoints_leave_drivers_unchanged() {
+        let hub = hub(1, 191).await;
+        let endpoint = "udps:127.0.0.1:0".to_string();
+        hub.update_endpoints(&[endpoint.clone()]).await.unwrap();
+
+        let result = hub
+            .update_endpoints(&["invalid:endpoint".to_string()])
+            .await;
+        assert!(result.is_err());
+        assert_eq!(
+            hub.endpoints().await.into_values().collect::<Vec<_>>(),
+            vec![endpoint]
+        );
+
+        hub.update_endpoints(&[]).await.unwrap();
+        assert!(hub.endpoints().await.is_empty());
+    }
+
+    #[tokio::test]
+    async fn latest_message_is_cached() {
+        let hub = hub(1, 191).await;
+        let mut receiver = hub.get_sender().subscribe();
+
+        let message = receiver.recv().await.unwrap();
+
+        // The routing task handles the message on its own time
+        let deadline = tokio::time::Instant::now() + tokio::time::Duration::from_secs(1);
+        let cached = loop {
+            if let Some(cached) = hub.latest_message(1, 191, message.message_id()) {
+                break cached;
+            }
+            assert!(
+                tokio::time::Instant::now() < deadline,
+                "HEARTBEAT should be cached"
+            );
+            tokio::time::sleep(tokio::time::Duration::from_millis(1)).await;
+        };
+        assert_eq!(cached.message_id(), message.message_id());
+        assert!(hub.latest_message(2, 191, message.message_id()).is_none());
+    }
 }
@@ -1,8 +1,11 @@
 mod cli;
+mod config;
 mod drivers;
 mod hub;
 mod logger;
 mod protocol;
+mod router;
+mod web;
 
 use std::sync::Arc;
 
@@ -17,59 +20,51 @@ async fn main() -> Result<()> {
     // Logger should start before everything else to register any log information
     logger::init();
 
-    let hub = hub::Hub::new(
-        100,
-        Arc::new(RwLock::new(
-            mavlink::ardupilotmega::MavComponent::MAV_COMP_ID_ONBOARD_COMPUTER as u8,
-        )),
-        Arc::new(RwLock::new(1)),
-        Arc::new(RwLock::new(1.)),
-    )
-    .await;
+    let hub = Arc::new(
+        hub::Hub::new(
+            cli::hub_buffer_size(),
+            Arc::new(RwLock::new(cli::hub_component_id())),
+            Arc::new(RwLock::new(cli::hub_system_id())),
+            Arc::new(RwLock::new(cli::hub_heartbeat_frequency())),
+            Arc::new(RwLock::new(cli::hub_heartbeat_mavtype())),
+            Arc::new(RwLock::new(cli::hub_heartbeat_autopilot())),
+        )
+        .await,
+    );
+    hub.router().set_streamreq_disable(cli::streamreq_disable());
 
-    // Endpoints creation
-    {
-        for endpoint in cli::tcp_client_endpoints() {
-            debug!("Creating TCP Client to {endpoint:?}");
-            hub.add_driver(Arc::new(drivers::tcp::client::TcpClient::new(&endpoint)))
-                .await?;
-        }
-        for endpoint in cli::tcp_server_endpoints() {
-            debug!("Creating TCP Server to {endpoint:?}");
-            hub.add_driver(Arc::new(drivers::tcp::server::TcpServer::new(&endpoint)))
-                .await?;
-        }
-        for endpoint in cli::udp_client_endpoints() {
-            debug!("Creating UDP Client to {endpoint:?}");
-            hub.add_driver(Arc::new(drivers::udp::client::UdpClient::new(&endpoint)))
-                .await?;
-        }
-        for endpoint in cli::udp_server_endpoints() {
-            debug!("Creating UDP Server to {endpoint:?}");
-            hub.add_driver(Arc::new(drivers::udp::server::UdpServer::new(&endpoint)))
-                .await?;
-        }
-        for endpoint in cli::udp_broadcast_endpoints() {
-            debug!("Creating UDP Broadcast to {endpoint:?}");
+    let web_task = cli::web_server().map(|address| {
+        let hub = hub.clone();
+        tokio::spawn(async move {
+            if let Err(error) = web::run(address, hub).await {
+                error!("HTTP server failed: {error:?}");
+            }
+        })
+    });
 
-            let mut s = endpoint.split(':');
-            let _ip = s.next().unwrap();
-            let port = s.next().unwrap();
-            let broadcast_ip = "255.255.255.255";
+    for endpoint in cli::endpoints() {
+        debug!("Creating driver for {endpoint:?}");
+        hub.add_endpoint(&endpoint).await?;
+    }
+
+    let config_task = cli::config_path().map(|path| {
+        let hub = hub.clone();
+        tokio::spawn(async move {
+            if let Err(error) = config::watch(path, hub).await {
+                error!("Config file watcher failed: {error:?}");
+            }
+        })
+    });
 
-            let endpoint = format!("{broadcast_ip}:{port}");
+    wait_ctrlc().await;
 
-            hub.add_driver(Arc::new(drivers::udp::client::UdpClient::new(&endpoint)))
-                .await?;
-            continue;
-        }
-        for _endpoint in cli::serial_endpoints() {
-            error!("Serial endpoint not implemented");
-            continue;
-        }
+    if let Some(web_task) = web_task {
+        web_task.abort();
     }
 
-    wait_ctrlc().await;
+    if let Some(config_task) = config_task {
+        config_task.abort();
+    }
 
     for (id, driver_info) in hub.drivers().await {
         debug!("Removing driver id {id:?} ({driver_info:?})");
@@ -1,24 +1,137 @@
 use std::ops::{Deref, DerefMut};
 
-use mavlink::MAVLinkV2MessageRaw;
+use anyhow::{anyhow, Result};
+use mavlink::{
+    ardupilotmega::MavMessage, MAVLinkV1MessageRaw, MAVLinkV2MessageRaw, MavHeader, MavlinkVersion,
+    Message,
+};
+use serde::{Deserialize, Serialize};
+
+/// A raw MAVLink frame, forwarded untouched regardless of its version
+#[derive(Debug, Clone)]
+pub enum MAVLinkMessageRaw {
+    V1(MAVLinkV1MessageRaw),
+    V2(MAVLinkV2MessageRaw),
+    /// A signed v2 frame, whose signature isn't kept by the library, so the whole frame is kept
+    /// along with it to be forwarded untouched
+    SignedV2(MAVLinkV2MessageRaw, Vec<u8>),
+}
+
+impl MAVLinkMessageRaw {
+    pub fn version(&self) -> MavlinkVersion {
+        match self {
+            Self::V1(_) => MavlinkVersion::V1,
+            Self::V2(_) | Self::SignedV2(..) => MavlinkVersion::V2,
+        }
+    }
+
+    /// Keeps the whole frame the message was read from, when it has bytes the library doesn't keep
+    /// (i.e. the signature of signed v2 frames)
+    pub fn with_frame(self, frame: &[u8]) -> Self {
+        match self {
+            Self::V2(message) if frame.len() > message.raw_bytes().len() => {
+                Self::SignedV2(message, frame.to_vec())
+            }
+            message => message,
+        }
+    }
+
+    pub fn raw_bytes(&self) -> &[u8] {
+        match self {
+            Self::V1(message) => message.raw_bytes(),
+            Self::V2(message) => message.raw_bytes(),
+            Self::SignedV2(_, frame) => frame,
+        }
+    }
+
+    pub fn header(&self) -> &[u8] {
+        match self {
+            Self::V1(message) => message.header(),
+            Self::V2(message) | Self::SignedV2(message, _) => message.header(),
+        }
+    }
+
+    pub fn payload(&self) -> &[u8] {
+        match self {
+            Self::V1(message) => message.payload(),
+            Self::V2(message) | Self::SignedV2(message, _) => message.payload(),
+        }
+    }
+
+    pub fn sequence(&self) -> u8 {
+        match self {
+            Self::V1(message) => message.sequence(),
+            Self::V2(message) | Self::SignedV2(message, _) => message.sequence(),
+        }
+    }
+
+    pub fn system_id(&self) -> u8 {
+        match self {
+            Self::V1(message) => message.system_id(),
+            Self::V2(message) | Self::SignedV2(message, _) => message.system_id(),
+        }
+    }
+
+    pub fn component_id(&self) -> u8 {
+        match self {
+            Self::V1(message) => message.component_id(),
+            Self::V2(message) | Self::SignedV2(message, _) => message.component_id(),
+        }
+    }
+
+    pub fn message_id(&self) -> u32 {
+        match self {
+            Self::V1(message) => message.message_id() as u32,
+            Self::V2(message) | Self::SignedV2(message, _) => message.message_id(),
+        }
+    }
+
+    /// Decodes the frame into its typed message, with the ardupilotmega dialect
+    pub fn decode(&self) -> Result<MavMessage> {
+        MavMessage::parse(self.version(), self.message_id(), self.payload())
+            .map_err(|error| anyhow!("Failed to parse message {}: {error:?}", self.message_id()))
+    }
+}
+
+impl From<MAVLinkV1MessageRaw> for MAVLinkMessageRaw {
+    fn from(message: MAVLinkV1MessageRaw) -> Self {
+        Self::V1(message)
+    }
+}
+
+impl From<MAVLinkV2MessageRaw> for MAVLinkMessageRaw {
+    fn from(message: MAVLinkV2MessageRaw) -> Self {
+        Self::V2(message)
+    }
+}
 
 #[derive(Debug, Clone)]
 pub struct Protocol {
     pub origin: String,
-    message: MAVLinkV2MessageRaw,
+    message: MAVLinkMessageRaw,
+    /// (target_system, target_component) of the message, extracted once for all the links
+    target: Option<(u8, u8)>,
 }
 
 impl Protocol {
-    pub fn new(origin: &str, message: MAVLinkV2MessageRaw) -> Self {
+    pub fn new(origin: &str, message: impl Into<MAVLinkMessageRaw>) -> Self {
+        let message = message.into();
+
         Self {
             origin: origin.to_string(),
+            target: crate::router::target_ids(&message),
             message,
         }
     }
+
+    /// (target_system, target_component) of the message, if it has them
+    pub fn target(&self) -> Option<(u8, u8)> {
+        self.target
+    }
 }
 
 impl Deref for Protocol {
-    type Target = MAVLinkV2MessageRaw;
+    type Target = MAVLinkMessageRaw;
 
     fn deref(&self) -> &Self::Target {
         &self.message
@@ -30,3 +143,113 @@ impl DerefMut for Protocol {
         &mut self.message
     }
 }
+
+/// Header fields of a decoded message
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct MAVLinkJSONHeader {
+    pub system_id: u8,
+    pub component_id: u8,
+    #[serde(default)]
+    pub sequence: u8,
+}
+
+/// A decoded MAVLink message, as exchanged in JSON with web clients, e.g.:
+/// {"header": {"system_id": 1, "component_id": 1, "sequence": 0}, "message": {"type": "HEARTBEAT", ...}}
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct MAVLinkJSON {
+    pub header: MAVLinkJSONHeader,
+    pub message: MavMessage,
+    /// Link the message came from, empty for messages sent by clients
+    #[serde(default, skip_deserializing)]
+    pub origin: String,
+}
+
+impl MAVLinkJSON {
+    /// Serializes the message into a MAVLink v2 frame
+    pub fn to_raw(&self) -> MAVLinkMessageRaw {
+        let header = MavHeader {
+            system_id: self.header.system_id,
+            component_id: self.header.component_id,
+            sequence: self.header.sequence,
+        };
+
+        let mut message_raw = MAVLinkV2MessageRaw::new();
+        message_raw.serialize_message(header, &self.message);
+        message_raw.into()
+    }
+}
+
+impl TryFrom<&Protocol> for MAVLinkJSON {
+    type Error = anyhow::Error;
+
+    fn try_from(message: &Protocol) -> Result<Self> {
+        Ok(Self {
+            header: MAVLinkJSONHeader {
+                system_id: message.system_id(),
+                component_id: message.component_id(),
+                sequence: message.sequence(),
+            },
+            message: message.decode()?,
+            origin: message.origin.clone(),
+        })
+    }
+}
+
+/// Frames shared by the tests of the router and the drivers
+#[cfg(test)]
+pub mod fixtures {
+    use mavlink::{
+        ardupilotmega::{MavAutopilot, MavMessage, MavModeFlag, MavState, MavType, HEARTBEAT_DATA},
+        MAVLinkV1MessageRaw, MAVLinkV2MessageRaw, MavHeader, MavlinkVersion,
+    };
+
+    use super::MAVLinkMessageRaw;
+
+    /// A HEARTBEAT of the given vehicle type, where the custom mode can carry any payload bytes
+    pub fn heartbeat(mavtype: MavType, custom_mode: u32) -> MavMessage {
+        MavMessage::HEARTBEAT(HEARTBEAT_DATA {
+            custom_mode,
+            mavtype,
+            autopilot: MavAutopilot::MAV_AUTOPILOT_ARDUPILOTMEGA,
+            base_mode: MavModeFlag::empty(),
+            system_status: MavState::MAV_STATE_ACTIVE,
+            mavlink_version: 0x3,
+        })
+    }
+
+    /// Serializes the message from (system_id, component_id) into a frame of the given version
+    pub fn frame(
+        version: MavlinkVersion,
+        system_id: u8,
+        component_id: u8,
+        sequence: u8,
+        message: &MavMessage,
+    ) -> MAVLinkMessageRaw {
+        let header = MavHeader {
+            system_id,
+            component_id,
+            sequence,
+        };
+
+        match version {
+            MavlinkVersion::V1 => {
+                let mut message_raw = MAVLinkV1MessageRaw::new();
+                message_raw.serialize_message(header, message);
+                message_raw.into()
+            }
+            MavlinkVersion::V2 => {
+                let mut message_raw = MAVLinkV2MessageRaw::new();
+                message_raw.serialize_message(header, message);
+                message_raw.into()
+            }
+        }
+    }
+
+    /// Bytes of a HEARTBEAT frame from a quadrotor (1,1)
+    pub fn heartbeat_frame(version: MavlinkVersion, sequence: u8) -> Vec<u8> {
+        let message = heartbeat(MavType::MAV_TYPE_QUADROTOR, 0);
+        frame(version, 1, 1, sequence, &message)
+            .raw_bytes()
+            .to_vec()
+    }
+}
@@ -0,0 +1,588 @@
+use std::{
+    collections::{HashMap, HashSet},
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        RwLock,
+    },
+    time::{Duration, Instant},
+};
+
+use mavlink::{
+    ardupilotmega::{
+        MavCmd, MavMessage, MavType, COMMAND_INT_DATA, COMMAND_LONG_DATA, HEARTBEAT_DATA,
+        REQUEST_DATA_STREAM_DATA,
+    },
+    Message, MessageData,
+};
+use tokio::sync::broadcast;
+use tracing::*;
+
+use crate::protocol::{MAVLinkMessageRaw, Protocol};
+
+/// Routes not refreshed for longer than this are considered gone
+const ROUTE_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Suppressed stream requests are logged at most once per this period, for each link
+const SUPPRESSION_LOG_PERIOD: Duration = Duration::from_secs(60);
+
+/// Where each (sysid, compid) was last seen, by link (the message origin)
+#[derive(Debug, Default)]
+struct RoutingTable {
+    routes: HashMap<(u8, u8), HashMap<String, Instant>>,
+    /// (sysid, compid) known not to be ground control stations, from their HEARTBEAT
+    non_ground_stations: HashSet<(u8, u8)>,
+    /// When the suppressed stream requests of each link were last logged, and how many were
+    /// suppressed since then
+    suppression_logs: HashMap<String, (Instant, u64)>,
+    /// When the routes and logs of the links that went silent were last removed
+    last_pruned: Option<Instant>,
+}
+
+impl RoutingTable {
+    /// Removes the routes and suppression logs of the links that went silent, since links that
+    /// reconnect usually come back with a new origin (e.g. a new TCP port)
+    fn prune(&mut self, now: Instant) {
+        self.routes.retain(|key, origins| {
+            origins.retain(|origin, last_seen| {
+                let is_alive = now.duration_since(*last_seen) < ROUTE_TIMEOUT;
+                if !is_alive {
+                    debug!("Route removed: {key:?} -> {origin:?}");
+                }
+                is_alive
+            });
+            !origins.is_empty()
+        });
+
+        self.suppression_logs
+            .retain(|origin, (last_log, suppressed)| {
+                if now.duration_since(*last_log) < SUPPRESSION_LOG_PERIOD {
+                    return true;
+                }
+
+                if *suppressed > 0 {
+                    info!(
+                        "Suppressed {suppressed} stream requests from ground control stations on {origin:?} in the last {:?}",
+                        now.duration_since(*last_log)
+                    );
+                }
+                false
+            });
+
+        self.last_pruned = Some(now);
+    }
+}
+
+/// Routing table of a hub, shared with its drivers: the receiving side learns routes before the
+/// messages reach the hub channel, and the sending side checks them for each link
+#[derive(Debug, Default)]
+pub struct Router {
+    table: RwLock<RoutingTable>,
+    /// Drops stream requests from ground control stations, see
+    /// [`Router::is_suppressed_stream_request`]
+    streamreq_disable: AtomicBool,
+}
+
+impl Router {
+    #[instrument(level = "debug", skip(self))]
+    pub fn set_streamreq_disable(&self, disable: bool) {
+        self.streamreq_disable.store(disable, Ordering::Relaxed);
+    }
+
+    /// Learns the route to the message's source and sends it to the hub channel, so the route is
+    /// already known by the time any link handles the message.
+    /// Suppressed stream requests are dropped here, reaching no link.
+    #[instrument(level = "trace", skip(self, hub_sender, message))]
+    pub fn send_to_hub(
+        &self,
+        hub_sender: &broadcast::Sender<Protocol>,
+        message: Protocol,
+    ) -> Result<usize, broadcast::error::SendError<Protocol>> {
+        // Messages originated by the hub itself (empty origin) don't come from any link
+        if !message.origin.is_empty() {
+            self.update(&message, &message.origin);
+
+            if self.is_suppressed_stream_request(&message) {
+                self.log_suppressed(&message);
+                return Ok(0);
+            }
+        }
+
+        hub_sender.send(message)
+    }
+
+    /// Learns the route to the message's source from the link it came from
+    #[instrument(level = "trace", skip(self, message))]
+    pub fn update(&self, message: &MAVLinkMessageRaw, origin: &str) {
+        let key = (message.system_id(), message.component_id());
+        let now = Instant::now();
+
+        let mut table = self.table.write().unwrap();
+        if !table
+            .last_pruned
+            .is_some_and(|last_pruned| now.duration_since(last_pruned) < ROUTE_TIMEOUT)
+        {
+            table.prune(now);
+        }
+
+        let origins = table.routes.entry(key).or_default();
+        match origins.get_mut(origin) {
+            Some(last_seen) => *last_seen = now,
+            None => {
+                debug!("Route added: {key:?} -> {origin:?}");
+                origins.insert(origin.to_string(), now);
+            }
+        }
+
+        if message.message_id() == HEARTBEAT_DATA::ID {
+            let Ok(MavMessage::HEARTBEAT(heartbeat)) =
+                MavMessage::parse(message.version(), message.message_id(), message.payload())
+            else {
+                return;
+            };
+
+            if heartbeat.mavtype == MavType::MAV_TYPE_GCS {
+                if table.non_ground_stations.remove(&key) {
+                    debug!("Ground control station found: {key:?} on {origin:?}");
+                }
+            } else {
+                table.non_ground_stations.insert(key);
+            }
+        }
+    }
+
+    /// Checks if the message is a REQUEST_DATA_STREAM or a SET_MESSAGE_INTERVAL command from a
+    /// ground control station while stream requests are disabled, so multiple ground control
+    /// stations don't fight over the stream rates of the vehicle.
+    /// Sources are treated as ground control stations until their HEARTBEAT tells otherwise, so
+    /// requests sent right after connecting are suppressed too.
+    #[instrument(level = "trace", skip(self, message))]
+    fn is_suppressed_stream_request(&self, message: &MAVLinkMessageRaw) -> bool {
+        if !self.streamreq_disable.load(Ordering::Relaxed) {
+            return false;
+        }
+
+        let message_id = message.message_id();
+        if !matches!(
+            message_id,
+            REQUEST_DATA_STREAM_DATA::ID | COMMAND_LONG_DATA::ID | COMMAND_INT_DATA::ID
+        ) {
+            return false;
+        }
+
+        let source = (message.system_id(), message.component_id());
+        if self
+            .table
+            .read()
+            .unwrap()
+            .non_ground_stations
+            .contains(&source)
+        {
+            return false;
+        }
+
+        match MavMessage::parse(message.version(), message_id, message.payload()) {
+            Ok(MavMessage::REQUEST_DATA_STREAM(_)) => true,
+            Ok(MavMessage::COMMAND_LONG(data)) => {
+                data.command == MavCmd::MAV_CMD_SET_MESSAGE_INTERVAL
+            }
+            Ok(MavMessage::COMMAND_INT(data)) => {
+                data.command == MavCmd::MAV_CMD_SET_MESSAGE_INTERVAL
+            }
+            _ => false,
+        }
+    }
+
+    /// Logs the suppressed stream requests of each link, at most once per
+    /// [`SUPPRESSION_LOG_PERIOD`]
+    fn log_suppressed(&self, message: &Protocol) {
+        let mut table = self.table.write().unwrap();
+        let now = Instant::now();
+
+        let Some((last_log, suppressed)) = table.suppression_logs.get_mut(&message.origin) else {
+            info!(
+                "Suppressed stream request (msgid {}) from ground control station ({},{}) on {:?}",
+                message.message_id(),
+                message.system_id(),
+                message.component_id(),
+                message.origin
+            );
+            table
+                .suppression_logs
+                .insert(message.origin.clone(), (now, 0));
+            return;
+        };
+
+        *suppressed += 1;
+        if now.duration_since(*last_log) >= SUPPRESSION_LOG_PERIOD {
+            info!(
+                "Suppressed {suppressed} stream requests from ground control stations on {:?} in the last {:?}",
+                message.origin,
+                now.duration_since(*last_log)
+            );
+            *last_log = now;
+            *suppressed = 0;
+        }
+    }
+
+    /// Checks if the message should be delivered to the given link.
+    ///
+    /// Untargeted and broadcast messages go to every link, targeted messages go only to links
+    /// where the target was seen. Messages to unknown targets are broadcast.
+    #[instrument(level = "trace", skip(self, message))]
+    pub fn should_forward(&self, message: &Protocol, origin: &str) -> bool {
+        let Some((target_system, target_component)) = message.target() else {
+            return true;
+        };
+
+        if target_system == 0 {
+            return true;
+        }
+
+        let table = self.table.read().unwrap();
+        let now = Instant::now();
+        let is_alive = |last_seen: &Instant| now.duration_since(*last_seen) < ROUTE_TIMEOUT;
+
+        if target_component != 0 {
+            if let Some(origins) = table.routes.get(&(target_system, target_component)) {
+                if origins.values().any(is_alive) {
+                    return origins.get(origin).is_some_and(is_alive);
+                }
+            }
+        }
+
+        let mut target_known = false;
+        for (known_origin, last_seen) in table
+            .routes
+            .iter()
+            .filter(|((system_id, _), _)| *system_id == target_system)
+            .flat_map(|(_, origins)| origins.iter())
+        {
+            if !is_alive(last_seen) {
+                continue;
+            }
+            if known_origin == origin {
+                return true;
+            }
+            target_known = true;
+        }
+
+        if !target_known {
+            trace!("Unknown target ({target_system},{target_component}), broadcasting");
+        }
+        !target_known
+    }
+}
+
+/// Extracts the (target_system, target_component) from messages that have them, only decoding
+/// the messages known to carry a target
+pub fn target_ids(message: &MAVLinkMessageRaw) -> Option<(u8, u8)> {
+    use mavlink::ardupilotmega::*;
+
+    if !matches!(
+        message.message_id(),
+        COMMAND_LONG_DATA::ID
+            | COMMAND_INT_DATA::ID
+            | COMMAND_ACK_DATA::ID
+            | PARAM_REQUEST_READ_DATA::ID
+            | PARAM_REQUEST_LIST_DATA::ID
+            | PARAM_SET_DATA::ID
+            | MISSION_REQUEST_LIST_DATA::ID
+            | MISSION_REQUEST_PARTIAL_LIST_DATA::ID
+            | MISSION_WRITE_PARTIAL_LIST_DATA::ID
+            | MISSION_COUNT_DATA::ID
+            | MISSION_ITEM_DATA::ID
+            | MISSION_ITEM_INT_DATA::ID
+            | MISSION_REQUEST_DATA::ID
+            | MISSION_REQUEST_INT_DATA::ID
+            | MISSION_SET_CURRENT_DATA::ID
+            | MISSION_CLEAR_ALL_DATA::ID
+            | MISSION_ACK_DATA::ID
+            | REQUEST_DATA_STREAM_DATA::ID
+            | SET_POSITION_TARGET_LOCAL_NED_DATA::ID
+            | SET_POSITION_TARGET_GLOBAL_INT_DATA::ID
+            | SET_ATTITUDE_TARGET_DATA::ID
+            | RC_CHANNELS_OVERRIDE_DATA::ID
+            | FILE_TRANSFER_PROTOCOL_DATA::ID
+            | LOG_REQUEST_LIST_DATA::ID
+            | LOG_REQUEST_DATA_DATA::ID
+            | LOG_REQUEST_END_DATA::ID
+            | LOG_ERASE_DATA::ID
+            | PING_DATA::ID
+            | SET_MODE_DATA::ID
+            | SET_GPS_GLOBAL_ORIGIN_DATA::ID
+            | MANUAL_CONTROL_DATA::ID
+    ) {
+        return None;
+    }
+
+    let message =
+        MavMessage::parse(message.version(), message.message_id(), message.payload()).ok()?;
+
+    use MavMessage::*;
+    let ids = match message {
+        COMMAND_LONG(data) => (data.target_system, data.target_component),
+        COMMAND_INT(data) => (data.target_system, data.target_component),
+        COMMAND_ACK(data) => (data.target_system, data.target_component),
+        PARAM_REQUEST_READ(data) => (data.target_system, data.target_component),
+        PARAM_REQUEST_LIST(data) => (data.target_system, data.target_component),
+        PARAM_SET(data) => (data.target_system, data.target_component),
+        MISSION_REQUEST_LIST(data) => (data.target_system, data.target_component),
+        MISSION_REQUEST_PARTIAL_LIST(data) => (data.target_system, data.target_component),
+        MISSION_WRITE_PARTIAL_LIST(data) => (data.target_system, data.target_component),
+        MISSION_COUNT(data) => (data.target_system, data.target_component),
+        MISSION_ITEM(data) => (data.target_system, data.target_component),
+        MISSION_ITEM_INT(data) => (data.target_system, data.target_component),
+        MISSION_REQUEST(data) => (data.target_system, data.target_component),
+        MISSION_REQUEST_INT(data) => (data.target_system, data.target_component),
+        MISSION_SET_CURRENT(data) => (data.target_system, data.target_component),
+        MISSION_CLEAR_ALL(data) => (data.target_system, data.target_component),
+        MISSION_ACK(data) => (data.target_system, data.target_component),
+        REQUEST_DATA_STREAM(data) => (data.target_system, data.target_component),
+        SET_POSITION_TARGET_LOCAL_NED(data) => (data.target_system, data.target_component),
+        SET_POSITION_TARGET_GLOBAL_INT(data) => (data.target_system, data.target_component),
+        SET_ATTITUDE_TARGET(data) => (data.target_system, data.target_component),
+        RC_CHANNELS_OVERRIDE(data) => (data.target_system, data.target_component),
+        FILE_TRANSFER_PROTOCOL(data) => (data.target_system, data.target_component),
+        LOG_REQUEST_LIST(data) => (data.target_system, data.target_component),
+        LOG_REQUEST_DATA(data) => (data.target_system, data.target_component),
+        LOG_REQUEST_END(data) => (data.target_system, data.target_component),
+        LOG_ERASE(data) => (data.target_system, data.target_component),
+        PING(data) => (data.target_system, data.target_component),
+        SET_MODE(data) => (data.target_system, 0),
+        SET_GPS_GLOBAL_ORIGIN(data) => (data.target_system, 0),
+        MANUAL_CONTROL(data) => (data.target, 0),
+        _ => return None,
+    };
+
+    Some(ids)
+}
+
+#[cfg(test)]
+mod tests {
+    use mavlink::{ardupilotmega::MavFrame, MavlinkVersion};
+
+    use super::*;
+    use crate::protocol::fixtures;
+
+    fn frame(origin: &str, system_id: u8, component_id: u8, message: &MavMessage) -> Protocol {
+        let message = fixtures::frame(MavlinkVersion::V2, system_id, component_id, 0, message);
+        Protocol::new(origin, message)
+    }
+
+    fn heartbeat(origin: &str, system_id: u8, component_id: u8, mavtype: MavType) -> Protocol {
+        frame(
+            origin,
+            system_id,
+            component_id,
+            &fixtures::heartbeat(mavtype, 0),
+        )
+    }
+
+    fn command(origin: &str, target_system: u8, target_component: u8, command: MavCmd) -> Protocol {
+        let message = MavMessage::COMMAND_LONG(COMMAND_LONG_DATA {
+            param1: 0.,
+            param2: 0.,
+            param3: 0.,
+            param4: 0.,
+            param5: 0.,
+            param6: 0.,
+            param7: 0.,
+            command,
+            target_system,
+            target_component,
+            confirmation: 0,
+        });
+        frame(origin, 255, 190, &message)
+    }
+
+    fn command_int(origin: &str, command: MavCmd) -> Protocol {
+        let message = MavMessage::COMMAND_INT(COMMAND_INT_DATA {
+            param1: 0.,
+            param2: 0.,
+            param3: 0.,
+            param4: 0.,
+            x: 0,
+            y: 0,
+            z: 0.,
+            command,
+            target_system: 1,
+            target_component: 1,
+            frame: MavFrame::MAV_FRAME_GLOBAL,
+            current: 0,
+            autocontinue: 0,
+        });
+        frame(origin, 255, 190, &message)
+    }
+
+    fn request_data_stream(origin: &str, system_id: u8, component_id: u8) -> Protocol {
+        let message = MavMessage::REQUEST_DATA_STREAM(REQUEST_DATA_STREAM_DATA {
+            req_message_rate: 10,
+            target_system: 1,
+            target_component: 1,
+            req_stream_id: 0,
+            start_stop: 1,
+        });
+        frame(origin, system_id, component_id, &message)
+    }
+
+    /// Sends the message to the hub through the router, returning if it reached the hub channel
+    fn reaches_hub(router: &Router, message: Protocol) -> bool {
+        let (hub_sender, mut hub_receiver) = broadcast::channel(10);
+        router.send_to_hub(&hub_sender, message).unwrap();
+        hub_receiver.try_recv().is_ok()
+    }
+
+    /// A router that knows a vehicle (1,1) on "vehicle" and a ground station (255,190) on "gcs"
+    fn router() -> Router {
+        let router = Router::default();
+        let (hub_sender, _hub_receiver) = broadcast::channel(10);
+
+        for message in [
+            heartbeat("vehicle", 1, 1, MavType::MAV_TYPE_QUADROTOR),
+            heartbeat("gcs", 255, 190, MavType::MAV_TYPE_GCS),
+        ] {
+            router.send_to_hub(&hub_sender, message).unwrap();
+        }
+
+        router
+    }
+
+    #[test]
+    fn untargeted_messages_go_everywhere() {
+        let router = router();
+        let message = heartbeat("vehicle", 1, 1, MavType::MAV_TYPE_QUADROTOR);
+
+        assert_eq!(message.target(), None);
+        for origin in ["vehicle", "gcs", "other"] {
+            assert!(router.should_forward(&message, origin));
+        }
+    }
+
+    #[test]
+    fn targeted_messages_go_only_to_the_target() {
+        let router = router();
+        let message = command("gcs", 1, 1, MavCmd::MAV_CMD_COMPONENT_ARM_DISARM);
+
+        assert_eq!(message.target(), Some((1, 1)));
+        assert!(router.should_forward(&message, "vehicle"));
+        assert!(!router.should_forward(&message, "gcs"));
+        assert!(!router.should_forward(&message, "other"));
+    }
+
+    #[test]
+    fn messages_to_a_system_go_to_its_links() {
+        let router = router();
+        let to_unknown_component = command("gcs", 1, 42, MavCmd::MAV_CMD_COMPONENT_ARM_DISARM);
+        let to_any_component = command("gcs", 1, 0, MavCmd::MAV_CMD_COMPONENT_ARM_DISARM);
+
+        for message in [to_unknown_component, to_any_component] {
+            assert!(router.should_forward(&message, "vehicle"));
+            assert!(!router.should_forward(&message, "other"));
+        }
+    }
+
+    #[test]
+    fn broadcast_and_unknown_targets_go_everywhere() {
+        let router = router();
+        let to_everyone = command("gcs", 0, 0, MavCmd::MAV_CMD_COMPONENT_ARM_DISARM);
+        let to_unknown = command("gcs", 2, 1, MavCmd::MAV_CMD_COMPONENT_ARM_DISARM);
+
+        for message in [to_everyone, to_unknown] {
+            for origin in ["vehicle", "gcs", "other"] {
+                assert!(router.should_forward(&message, origin));
+            }
+        }
+    }
+
+    #[test]
+    fn routers_are_independent() {
+        let router = router();
+        let other_router = Router::default();
+        let message = command("gcs", 1, 1, MavCmd::MAV_CMD_COMPONENT_ARM_DISARM);
+
+        assert!(!router.should_forward(&message, "other"));
+        assert!(other_router.should_forward(&message, "other"));
+    }
+
+    #[test]
+    fn stream_requests_from_ground_stations_are_suppressed() {
+        let router = router();
+        router.set_streamreq_disable(true);
+
+        for message in [
+            request_data_stream("gcs", 255, 190),
+            command("gcs", 1, 1, MavCmd::MAV_CMD_SET_MESSAGE_INTERVAL),
+            command_int("gcs", MavCmd::MAV_CMD_SET_MESSAGE_INTERVAL),
+        ] {
+            assert!(!reaches_hub(&router, message));
+        }
+
+        // Other commands still go through
+        assert!(reaches_hub(
+            &router,
+            command("gcs", 1, 1, MavCmd::MAV_CMD_COMPONENT_ARM_DISARM)
+        ));
+        assert!(reaches_hub(
+            &router,
+            command_int("gcs", MavCmd::MAV_CMD_DO_REPOSITION)
+        ));
+    }
+
+    #[test]
+    fn stream_requests_before_any_heartbeat_are_suppressed() {
+        let router = Router::default();
+        router.set_streamreq_disable(true);
+
+        assert!(!reaches_hub(&router, request_data_stream("gcs", 255, 190)));
+    }
+
+    #[test]
+    fn stream_requests_from_non_ground_stations_go_through() {
+        let router = router();
+        router.set_streamreq_disable(true);
+
+        // e.g.: a companion computer on the vehicle side
+        assert!(reaches_hub(&router, request_data_stream("vehicle", 1, 1)));
+    }
+
+    #[test]
+    fn stream_requests_go_through_when_enabled() {
+        let router = router();
+
+        assert!(reaches_hub(&router, request_data_stream("gcs", 255, 190)));
+    }
+
+    #[test]
+    fn hub_stream_requests_are_not_suppressed() {
+        let router = Router::default();
+        router.set_streamreq_disable(true);
+
+        assert!(reaches_hub(&router, request_data_stream("", 1, 191)));
+    }
+
+    #[test]
+    fn silent_links_are_pruned() {
+        let router = router();
+        router.set_streamreq_disable(true);
+        assert!(!reaches_hub(&router, request_data_stream("gcs", 255, 190)));
+
+        let mut table = router.table.write().unwrap();
+        let later = Instant::now() + SUPPRESSION_LOG_PERIOD.max(ROUTE_TIMEOUT);
+        table.prune(later);
+
+        assert!(table.routes.is_empty());
+        assert!(table.suppression_logs.is_empty());
+    }
+
+    #[test]
+    fn hub_messages_dont_create_routes() {
+        let router = Router::default();
+        let (hub_sender, _hub_receiver) = broadcast::channel(10);
+        router
+            .send_to_hub(&hub_sender, heartbeat("", 1, 1, MavType::MAV_TYPE_GCS))
+            .unwrap();
+
+        let message = command("gcs", 1, 1, MavCmd::MAV_CMD_COMPONENT_ARM_DISARM);
+        assert!(router.should_forward(&message, "vehicle"));
+    }
+}
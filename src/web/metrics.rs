@@ -0,0 +1,148 @@
+use std::{collections::HashMap, fmt::Write};
+
+use crate::{
+    drivers::{stats::DriverStats, DriverInfo},
+    hub::HubStats,
+};
+
+const PREFIX: &str = "mavlink_server";
+
+/// Renders the hub and driver statistics in the Prometheus text exposition format
+pub fn render(
+    hub_stats: &HubStats,
+    drivers: &HashMap<u64, DriverInfo>,
+    drivers_stats: &HashMap<u64, DriverStats>,
+) -> String {
+    let mut output = String::new();
+
+    write_header(
+        &mut output,
+        "messages_routed_total",
+        "counter",
+        "Messages that went through the hub",
+    );
+    writeln!(
+        output,
+        "{PREFIX}_messages_routed_total {}",
+        hub_stats.messages_routed
+    )
+    .unwrap();
+
+    write_header(
+        &mut output,
+        "hub_lagged_messages_total",
+        "counter",
+        "Messages missed by the hub because its broadcast channel was full",
+    );
+    writeln!(
+        output,
+        "{PREFIX}_hub_lagged_messages_total {}",
+        hub_stats.lagged_messages
+    )
+    .unwrap();
+
+    write_header(
+        &mut output,
+        "messages_total",
+        "counter",
+        "Messages that went through the hub, per message id",
+    );
+    let mut messages_per_msgid: Vec<_> = hub_stats.messages_per_msgid.iter().collect();
+    messages_per_msgid.sort();
+    for (msgid, count) in messages_per_msgid {
+        writeln!(
+            output,
+            "{PREFIX}_messages_total{{msgid=\"{msgid}\"}} {count}"
+        )
+        .unwrap();
+    }
+
+    let mut ids: Vec<_> = drivers_stats.keys().copied().collect();
+    ids.sort();
+
+    let driver_metrics: [(&str, &str, &str, fn(&DriverStats) -> f64); 10] = [
+        (
+            "driver_messages_received_total",
+            "counter",
+            "Messages received from the link",
+            |stats| stats.messages_received as f64,
+        ),
+        (
+            "driver_bytes_received_total",
+            "counter",
+            "Bytes of valid messages received from the link",
+            |stats| stats.bytes_received as f64,
+        ),
+        (
+            "driver_messages_sent_total",
+            "counter",
+            "Messages sent to the link",
+            |stats| stats.messages_sent as f64,
+        ),
+        (
+            "driver_bytes_sent_total",
+            "counter",
+            "Bytes of messages sent to the link",
+            |stats| stats.bytes_sent as f64,
+        ),
+        (
+            "driver_parse_errors_total",
+            "counter",
+            "Invalid frames received from the link",
+            |stats| stats.parse_errors as f64,
+        ),
+        (
+            "driver_bytes_discarded_total",
+            "counter",
+            "Bytes received from the link that were not part of a valid frame",
+            |stats| stats.bytes_discarded as f64,
+        ),
+        (
+            "driver_lagged_messages_total",
+            "counter",
+            "Messages dropped because the driver couldn't keep up with the hub",
+            |stats| stats.lagged_messages as f64,
+        ),
+        (
+            "driver_reconnections_total",
+            "counter",
+            "Times the driver had to reconnect its link",
+            |stats| stats.reconnections as f64,
+        ),
+        (
+            "driver_last_message_timestamp_seconds",
+            "gauge",
+            "UNIX timestamp of the last message received from the link",
+            |stats| stats.last_message_time_us.unwrap_or_default() as f64 / 1e6,
+        ),
+        (
+            "driver_peers",
+            "gauge",
+            "Connected peers, e.g.: TCP clients of a TcpServer or UDP clients known to a UdpServer",
+            |stats| stats.peers.len() as f64,
+        ),
+    ];
+
+    for (name, kind, help, value) in driver_metrics {
+        write_header(&mut output, name, kind, help);
+        for id in &ids {
+            let driver_name = drivers
+                .get(id)
+                .map(|info| info.name.as_str())
+                .unwrap_or_default();
+            writeln!(
+                output,
+                "{PREFIX}_{name}{{id=\"{id}\",name=\"{driver_name}\"}} {}",
+                value(&drivers_stats[id])
+            )
+            .unwrap();
+        }
+    }
+
+    output
+}
+
+fn write_header(output: &mut String, name: &str, kind: &str, help: &str) {
+    writeln!(output, "# HELP {PREFIX}_{name} {help}").unwrap();
+    writeln!(output, "# TYPE {PREFIX}_{name} {kind}").unwrap();
+}
@@ -0,0 +1,253 @@
+mod metrics;
+
+use std::{collections::HashMap, convert::Infallible, net::SocketAddr, sync::Arc};
+
+use anyhow::Result;
+use axum::{
+    extract::{Path, Query, State},
+    http::{header, StatusCode},
+    response::{
+        sse::{Event, KeepAlive, Sse},
+        IntoResponse,
+    },
+    routing::{delete, get, post},
+    Json, Router,
+};
+use futures::Stream;
+use mavlink::{ardupilotmega::MavMessage, Message};
+use serde::Deserialize;
+use tokio::sync::broadcast;
+use tracing::*;
+
+use crate::{
+    drivers::{self, stats::DriverStats, DriverInfo},
+    hub::{Hub, HubSettings, HubSettingsUpdate},
+    protocol::{MAVLinkJSON, MAVLinkJSONHeader, Protocol},
+};
+
+#[derive(Debug, Deserialize)]
+struct CreateDriverRequest {
+    /// Endpoint in the same format accepted by the CLI, e.g.: "tcpc:127.0.0.1:5760"
+    endpoint: String,
+}
+
+/// Message to be sent by the hub, e.g.:
+/// {"message": {"type": "COMMAND_LONG", ...}, "header": {"system_id": 255}}
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct SendMessageRequest {
+    message: MavMessage,
+    #[serde(default)]
+    header: HeaderOverrides,
+}
+
+/// Header fields replacing the ones of the hub, which are used for the missing fields
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+struct HeaderOverrides {
+    system_id: Option<u8>,
+    component_id: Option<u8>,
+    sequence: Option<u8>,
+}
+
+/// Restricts the streamed messages, where the missing fields match any message
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+struct StreamQuery {
+    system_id: Option<u8>,
+    component_id: Option<u8>,
+    /// Message name, e.g.: "ATTITUDE"
+    name: Option<String>,
+}
+
+/// Serves the HTTP management API until it fails
+#[instrument(level = "debug", skip(hub))]
+pub async fn run(address: SocketAddr, hub: Arc<Hub>) -> Result<()> {
+    let router = Router::new()
+        .route("/drivers", get(list_drivers).post(create_driver))
+        .route("/drivers/:id", delete(remove_driver))
+        .route("/stats", get(driver_stats))
+        .route("/metrics", get(metrics))
+        .route("/hub", get(hub_settings).patch(update_hub_settings))
+        .route(
+            "/mavlink/vehicles/:system_id/components/:component_id/messages/:name",
+            get(latest_message),
+        )
+        .route("/mavlink", post(send_message))
+        .route("/mavlink/stream", get(stream_messages))
+        .with_state(hub);
+
+    let listener = tokio::net::TcpListener::bind(address).await?;
+    info!("HTTP server listening on {address}");
+
+    axum::serve(listener, router).await?;
+
+    Ok(())
+}
+
+async fn list_drivers(State(hub): State<Arc<Hub>>) -> Json<HashMap<u64, DriverInfo>> {
+    Json(hub.drivers().await)
+}
+
+async fn driver_stats(State(hub): State<Arc<Hub>>) -> Json<HashMap<u64, DriverStats>> {
+    Json(hub.stats().await)
+}
+
+async fn metrics(State(hub): State<Arc<Hub>>) -> impl IntoResponse {
+    let body = metrics::render(&hub.hub_stats(), &hub.drivers().await, &hub.stats().await);
+
+    ([(header::CONTENT_TYPE, "text/plain; version=0.0.4")], body)
+}
+
+async fn hub_settings(State(hub): State<Arc<Hub>>) -> Json<HubSettings> {
+    Json(hub.settings().await)
+}
+
+async fn update_hub_settings(
+    State(hub): State<Arc<Hub>>,
+    Json(update): Json<HubSettingsUpdate>,
+) -> Result<Json<HubSettings>, (StatusCode, String)> {
+    hub.update_settings(update)
+        .await
+        .map_err(|error| (StatusCode::BAD_REQUEST, error.to_string()))?;
+
+    let settings = hub.settings().await;
+    info!("Hub settings updated: {settings:?}");
+    Ok(Json(settings))
+}
+
+async fn create_driver(
+    State(hub): State<Arc<Hub>>,
+    Json(request): Json<CreateDriverRequest>,
+) -> Result<Json<u64>, (StatusCode, String)> {
+    let driver = drivers::create_driver(&request.endpoint)
+        .map_err(|error| (StatusCode::BAD_REQUEST, error.to_string()))?;
+
+    let id = hub
+        .add_driver(driver, &request.endpoint)
+        .await
+        .map_err(|error| (StatusCode::INTERNAL_SERVER_ERROR, error.to_string()))?;
+
+    info!("Driver id {id:?} created for {:?}", request.endpoint);
+    Ok(Json(id))
+}
+
+async fn remove_driver(
+    State(hub): State<Arc<Hub>>,
+    Path(id): Path<u64>,
+) -> Result<StatusCode, (StatusCode, String)> {
+    hub.remove_driver(id)
+        .await
+        .map_err(|error| (StatusCode::NOT_FOUND, error.to_string()))?;
+
+    info!("Driver id {id:?} removed");
+    Ok(StatusCode::NO_CONTENT)
+}
+
+/// Message id from its name, case-insensitive
+fn message_id(name: &str) -> Result<u32, (StatusCode, String)> {
+    MavMessage::message_id_from_name(&name.to_uppercase()).map_err(|_| {
+        (
+            StatusCode::BAD_REQUEST,
+            format!("Unknown message: {name:?}"),
+        )
+    })
+}
+
+async fn latest_message(
+    State(hub): State<Arc<Hub>>,
+    Path((system_id, component_id, name)): Path<(u8, u8, String)>,
+) -> Result<Json<MAVLinkJSON>, (StatusCode, String)> {
+    let message_id = message_id(&name)?;
+
+    let message = hub
+        .latest_message(system_id, component_id, message_id)
+        .ok_or_else(|| {
+            (
+                StatusCode::NOT_FOUND,
+                format!("No {name} received from ({system_id},{component_id})"),
+            )
+        })?;
+
+    MAVLinkJSON::try_from(&message)
+        .map(Json)
+        .map_err(|error| (StatusCode::INTERNAL_SERVER_ERROR, error.to_string()))
+}
+
+/// Streams every message that goes through the hub as server-sent events, named after the message
+async fn stream_messages(
+    State(hub): State<Arc<Hub>>,
+    Query(query): Query<StreamQuery>,
+) -> Result<Sse<impl Stream<Item = Result<Event, Infallible>>>, (StatusCode, String)> {
+    let message_id = query.name.as_deref().map(message_id).transpose()?;
+    let system_id = query.system_id;
+    let component_id = query.component_id;
+
+    let receiver = hub.get_sender().subscribe();
+
+    let stream = futures::stream::unfold(receiver, move |mut receiver| async move {
+        loop {
+            let message = match receiver.recv().await {
+                Ok(message) => message,
+                Err(broadcast::error::RecvError::Closed) => return None,
+                Err(broadcast::error::RecvError::Lagged(count)) => {
+                    warn!("Message stream lagged by {count} messages.");
+                    continue;
+                }
+            };
+
+            if system_id.is_some_and(|id| id != message.system_id())
+                || component_id.is_some_and(|id| id != message.component_id())
+                || message_id.is_some_and(|id| id != message.message_id())
+            {
+                continue;
+            }
+
+            let Ok(message) = MAVLinkJSON::try_from(&message) else {
+                continue; // Not in the dialect
+            };
+
+            match Event::default()
+                .event(message.message.message_name())
+                .json_data(&message)
+            {
+                Ok(event) => return Some((Ok(event), receiver)),
+                Err(error) => {
+                    error!("Failed to serialize message: {error:?}");
+                    continue;
+                }
+            }
+        }
+    });
+
+    Ok(Sse::new(stream).keep_alive(KeepAlive::default()))
+}
+
+/// Serializes the message with the hub identity, unless overridden, and sends it to the links
+async fn send_message(
+    State(hub): State<Arc<Hub>>,
+    Json(request): Json<SendMessageRequest>,
+) -> Result<Json<MAVLinkJSON>, (StatusCode, String)> {
+    let settings = hub.settings().await;
+
+    let message = MAVLinkJSON {
+        header: MAVLinkJSONHeader {
+            system_id: request.header.system_id.unwrap_or(settings.system_id),
+            component_id: request.header.component_id.unwrap_or(settings.component_id),
+            sequence: request
+                .header
+                .sequence
+                .unwrap_or_else(|| hub.next_sequence()),
+        },
+        message: request.message,
+        origin: String::new(),
+    };
+
+    // Originated by the hub itself, like its HEARTBEAT, so no link is skipped as its origin
+    hub.get_sender()
+        .send(Protocol::new(&message.origin, message.to_raw()))
+        .map_err(|error| (StatusCode::INTERNAL_SERVER_ERROR, error.to_string()))?;
+
+    debug!("Message sent from the HTTP API: {message:?}");
+    Ok(Json(message))
+}